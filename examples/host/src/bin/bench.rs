@@ -0,0 +1,100 @@
+use host::{generate_task, Task};
+use methods::METHOD_ELF;
+use risc0_zkvm::{ExecutorEnv, ExecutorImpl};
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use l2r0_profiler_host::CycleTracer;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    // no trace callback at all
+    Executor,
+    // a trace callback that only counts the instructions
+    Callback,
+    // a trace callback that runs the cycle tracer
+    Tracer,
+}
+
+fn run(task: &Task, mode: Mode) -> (Duration, u64) {
+    let num_instructions = Rc::new(RefCell::new(0u64));
+    let cycle_tracer = Rc::new(RefCell::new(CycleTracer::default()));
+    cycle_tracer.borrow_mut().load_elf(METHOD_ELF).unwrap();
+
+    let mut builder = ExecutorEnv::builder();
+    builder
+        .write_slice(&task.a)
+        .write_slice(&task.b)
+        .write_slice(&task.long_form_c)
+        .write_slice(&task.k)
+        .write_slice(&task.long_form_kn);
+    if mode == Mode::Callback {
+        builder.trace_callback(|e| {
+            if let risc0_zkvm::TraceEvent::InstructionStart { .. } = e {
+                *num_instructions.borrow_mut() += 1;
+            }
+            Ok(())
+        });
+    }
+    if mode == Mode::Tracer {
+        builder.trace_callback(|e| {
+            cycle_tracer.borrow_mut().handle_event(e);
+            Ok(())
+        });
+    }
+    let env = builder.build().unwrap();
+
+    let start = Instant::now();
+    let mut exec = ExecutorImpl::from_elf(env, METHOD_ELF).unwrap();
+    let _ = exec.run().unwrap();
+    let elapsed = start.elapsed();
+
+    let num_instructions = match mode {
+        Mode::Tracer => cycle_tracer.borrow().num_instructions as u64,
+        _ => *num_instructions.borrow(),
+    };
+    (elapsed, num_instructions)
+}
+
+// cargo run --release --bin bench -- <number of runs>
+fn main() {
+    let num_runs = match std::env::args().nth(1).map(|arg| arg.parse::<u32>()) {
+        None => 5,
+        Some(Ok(num_runs)) if num_runs > 0 => num_runs,
+        Some(_) => {
+            eprintln!("usage: bench [number of runs, at least 1, 5 by default]");
+            std::process::exit(2);
+        }
+    };
+    let task = generate_task();
+
+    let mut results = Vec::new();
+    for (name, mode) in [
+        ("executor", Mode::Executor),
+        ("callback", Mode::Callback),
+        ("tracer", Mode::Tracer),
+    ] {
+        let mut total = Duration::ZERO;
+        let mut num_instructions = 0;
+        for _ in 0..num_runs {
+            let (elapsed, n) = run(&task, mode);
+            total += elapsed;
+            num_instructions = n;
+        }
+        let average = total / num_runs;
+        results.push((name, average, num_instructions));
+    }
+
+    let baseline = results[0].1.as_secs_f64();
+    let num_instructions = results[2].2;
+    for (name, average, _) in results.iter() {
+        println!(
+            "{:>8}: {:>10.2} ms per run, {:>6.2} M instructions per second, {:+.1}% over the executor",
+            name,
+            average.as_secs_f64() * 1000.0,
+            num_instructions as f64 / average.as_secs_f64() / 1_000_000.0,
+            (average.as_secs_f64() / baseline - 1.0) * 100.0,
+        );
+    }
+}
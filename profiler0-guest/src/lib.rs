@@ -1,4 +1,5 @@
 #![allow(unused_imports)]
+#![cfg_attr(all(target_os = "zkvm", feature = "print-trace"), feature(asm_const))]
 pub use self::inner::*;
 
 #[macro_use]
@@ -13,6 +14,15 @@ pub mod inner {
     pub static mut TRACE_MSG_LEN_CHANNEL: u32 = 0;
     #[no_mangle]
     pub static mut TRACE_SIGNAL_CHANNEL: u32 = 0;
+    // the span ID, then the words that start and stop a span
+    #[no_mangle]
+    pub static mut TRACE_SPAN_CHANNEL: [u32; 3] = [0u32; 3];
+
+    // the version of the layout above and of the handshake, which `init_trace_logger` also sends with a `li`
+    pub const PROTOCOL_VERSION: u32 = 2;
+    #[no_mangle]
+    #[used]
+    pub static TRACE_PROTOCOL_VERSION: u32 = PROTOCOL_VERSION;
 
     #[inline(always)]
     pub fn init_trace_logger() {
@@ -24,15 +34,71 @@ pub mod inner {
                 la x0, TRACE_MSG_CHANNEL
                 la x0, TRACE_MSG_LEN_CHANNEL
                 la x0, TRACE_SIGNAL_CHANNEL
+                la x0, TRACE_SPAN_CHANNEL
+                li x0, {v}
                 nop
-            "#
+            "#,
+                v = const PROTOCOL_VERSION
             );
         }
     }
 
+    // set on the length word when only the address of the name is sent to the host
+    pub const TRACE_INTERNED_NAME: u32 = 0x8000_0000;
+
+    // separates the category from the name of a timer in the message
+    pub const TRACE_CATEGORY_SEPARATOR: u8 = 0x1f;
+
+    // marks the instructions of a timer macro so that the host can measure their cost
+    #[cfg(feature = "calibrate")]
+    #[doc(hidden)]
+    #[macro_export]
+    macro_rules! __trace_instrumentation_begin {
+        () => {{
+            unsafe {
+                core::arch::asm!(
+                    r#"
+                        li x0, 0x5A1
+                    "#
+                );
+            }
+        }};
+    }
+
+    #[cfg(feature = "calibrate")]
+    #[doc(hidden)]
+    #[macro_export]
+    macro_rules! __trace_instrumentation_end {
+        () => {{
+            unsafe {
+                core::arch::asm!(
+                    r#"
+                        li x0, 0x5A0
+                    "#
+                );
+            }
+        }};
+    }
+
+    #[cfg(not(feature = "calibrate"))]
+    #[doc(hidden)]
+    #[macro_export]
+    macro_rules! __trace_instrumentation_begin {
+        () => {{}};
+    }
+
+    #[cfg(not(feature = "calibrate"))]
+    #[doc(hidden)]
+    #[macro_export]
+    macro_rules! __trace_instrumentation_end {
+        () => {{}};
+    }
+
+    #[cfg(not(feature = "interned-names"))]
     #[macro_export]
     macro_rules! start_timer {
         ($msg: expr) => {{
+            __trace_instrumentation_begin!();
             unsafe {
                 let len = $msg.len();
                 core::ptr::copy($msg.as_ptr(), TRACE_MSG_CHANNEL.0.as_mut_ptr(), len);
@@ -44,12 +110,41 @@ pub mod inner {
                 );
                 core::ptr::write_volatile((&mut TRACE_MSG_LEN_CHANNEL) as *mut u32, len as u32);
             }
+            __trace_instrumentation_end!();
+        }};
+    }
+
+    #[cfg(feature = "interned-names")]
+    #[macro_export]
+    macro_rules! start_timer {
+        ($msg: expr) => {{
+            __trace_instrumentation_begin!();
+            unsafe {
+                // the host reads the name from the guest ELF, so it must live in read-only data
+                let msg: &'static str = $msg;
+                core::ptr::write_volatile(
+                    TRACE_MSG_CHANNEL.0.as_mut_ptr() as *mut u32,
+                    msg.as_ptr() as u32,
+                );
+                // prevent out-of-order execution
+                core::arch::asm!(
+                    r#"
+                        nop
+                    "#
+                );
+                core::ptr::write_volatile(
+                    (&mut TRACE_MSG_LEN_CHANNEL) as *mut u32,
+                    msg.len() as u32 | TRACE_INTERNED_NAME,
+                );
+            }
+            __trace_instrumentation_end!();
         }};
     }
 
     #[macro_export]
     macro_rules! stop_timer {
         () => {{
+            __trace_instrumentation_begin!();
             unsafe {
                 core::ptr::write_volatile((&mut TRACE_SIGNAL_CHANNEL) as *mut u32, 0u32);
                 core::arch::asm!(
@@ -58,6 +153,7 @@ pub mod inner {
                     "#
                 );
             }
+            __trace_instrumentation_end!();
         }};
     }
 
@@ -68,6 +164,162 @@ pub mod inner {
             start_timer!($msg);
         }};
     }
+
+    // timers in a category are nested among themselves, independently of other categories
+    #[cfg(not(feature = "interned-names"))]
+    #[macro_export]
+    macro_rules! start_category_timer {
+        ($category: expr, $msg: expr) => {{
+            __trace_instrumentation_begin!();
+            unsafe {
+                let category_len = $category.len();
+                let len = $msg.len();
+                let ptr = TRACE_MSG_CHANNEL.0.as_mut_ptr();
+                core::ptr::copy($category.as_ptr(), ptr, category_len);
+                *ptr.add(category_len) = TRACE_CATEGORY_SEPARATOR;
+                core::ptr::copy($msg.as_ptr(), ptr.add(category_len + 1), len);
+                // prevent out-of-order execution
+                core::arch::asm!(
+                    r#"
+                        nop
+                    "#
+                );
+                core::ptr::write_volatile(
+                    (&mut TRACE_MSG_LEN_CHANNEL) as *mut u32,
+                    (category_len + 1 + len) as u32,
+                );
+            }
+            __trace_instrumentation_end!();
+        }};
+    }
+
+    // the name is looked up as a single string, so both must be literals
+    #[cfg(feature = "interned-names")]
+    #[macro_export]
+    macro_rules! start_category_timer {
+        ($category: expr, $msg: expr) => {{
+            start_timer!(concat!($category, "\x1f", $msg));
+        }};
+    }
+
+    #[cfg(not(feature = "interned-names"))]
+    #[macro_export]
+    macro_rules! stop_category_timer {
+        ($category: expr) => {{
+            __trace_instrumentation_begin!();
+            unsafe {
+                let len = $category.len();
+                core::ptr::copy($category.as_ptr(), TRACE_MSG_CHANNEL.0.as_mut_ptr(), len);
+                // prevent out-of-order execution
+                core::arch::asm!(
+                    r#"
+                        nop
+                    "#
+                );
+                core::ptr::write_volatile((&mut TRACE_SIGNAL_CHANNEL) as *mut u32, len as u32);
+            }
+            __trace_instrumentation_end!();
+        }};
+    }
+
+    #[cfg(feature = "interned-names")]
+    #[macro_export]
+    macro_rules! stop_category_timer {
+        ($category: expr) => {{
+            __trace_instrumentation_begin!();
+            unsafe {
+                let category: &'static str = $category;
+                core::ptr::write_volatile(
+                    TRACE_MSG_CHANNEL.0.as_mut_ptr() as *mut u32,
+                    category.as_ptr() as u32,
+                );
+                // prevent out-of-order execution
+                core::arch::asm!(
+                    r#"
+                        nop
+                    "#
+                );
+                core::ptr::write_volatile(
+                    (&mut TRACE_SIGNAL_CHANNEL) as *mut u32,
+                    category.len() as u32 | TRACE_INTERNED_NAME,
+                );
+            }
+            __trace_instrumentation_end!();
+        }};
+    }
+
+    #[macro_export]
+    macro_rules! stop_start_category_timer {
+        ($category: expr, $msg: expr) => {{
+            stop_category_timer!($category);
+            start_category_timer!($category, $msg);
+        }};
+    }
+
+    #[cfg(not(feature = "interned-names"))]
+    #[macro_export]
+    macro_rules! start_span {
+        ($id: expr, $msg: expr) => {{
+            __trace_instrumentation_begin!();
+            unsafe {
+                let len = $msg.len();
+                core::ptr::copy($msg.as_ptr(), TRACE_MSG_CHANNEL.0.as_mut_ptr(), len);
+                core::ptr::write_volatile(TRACE_SPAN_CHANNEL.as_mut_ptr(), $id as u32);
+                // prevent out-of-order execution
+                core::arch::asm!(
+                    r#"
+                        nop
+                    "#
+                );
+                core::ptr::write_volatile(TRACE_SPAN_CHANNEL.as_mut_ptr().add(1), len as u32);
+            }
+            __trace_instrumentation_end!();
+        }};
+    }
+
+    #[cfg(feature = "interned-names")]
+    #[macro_export]
+    macro_rules! start_span {
+        ($id: expr, $msg: expr) => {{
+            __trace_instrumentation_begin!();
+            unsafe {
+                let msg: &'static str = $msg;
+                core::ptr::write_volatile(
+                    TRACE_MSG_CHANNEL.0.as_mut_ptr() as *mut u32,
+                    msg.as_ptr() as u32,
+                );
+                core::ptr::write_volatile(TRACE_SPAN_CHANNEL.as_mut_ptr(), $id as u32);
+                // prevent out-of-order execution
+                core::arch::asm!(
+                    r#"
+                        nop
+                    "#
+                );
+                core::ptr::write_volatile(
+                    TRACE_SPAN_CHANNEL.as_mut_ptr().add(1),
+                    msg.len() as u32 | TRACE_INTERNED_NAME,
+                );
+            }
+            __trace_instrumentation_end!();
+        }};
+    }
+
+    // spans are matched by ID, so they can overlap and stop in any order
+    #[macro_export]
+    macro_rules! stop_span {
+        ($id: expr) => {{
+            __trace_instrumentation_begin!();
+            unsafe {
+                core::ptr::write_volatile(TRACE_SPAN_CHANNEL.as_mut_ptr().add(2), $id as u32);
+                core::arch::asm!(
+                    r#"
+                        nop
+                    "#
+                );
+            }
+            __trace_instrumentation_end!();
+        }};
+    }
 }
 
 #[macro_use]
@@ -93,4 +345,42 @@ pub mod inner {
             let _ = $msg;
         }};
     }
+
+    #[macro_export]
+    macro_rules! start_category_timer {
+        ($category: expr, $msg: expr) => {{
+            let _ = $category;
+            let _ = $msg;
+        }};
+    }
+
+    #[macro_export]
+    macro_rules! stop_category_timer {
+        ($category: expr) => {{
+            let _ = $category;
+        }};
+    }
+
+    #[macro_export]
+    macro_rules! stop_start_category_timer {
+        ($category: expr, $msg: expr) => {{
+            let _ = $category;
+            let _ = $msg;
+        }};
+    }
+
+    #[macro_export]
+    macro_rules! start_span {
+        ($id: expr, $msg: expr) => {{
+            let _ = $id;
+            let _ = $msg;
+        }};
+    }
+
+    #[macro_export]
+    macro_rules! stop_span {
+        ($id: expr) => {{
+            let _ = $id;
+        }};
+    }
 }
@@ -0,0 +1,104 @@
+use crate::CycleTracer;
+
+// a page is hashed as 16 blocks of 64 bytes when it is loaded or written back
+pub const PAGE_CYCLES: u32 = 1094;
+
+// why an instruction takes more than one cycle, the pages are page indices
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum CycleCause {
+    SegmentBoundary,
+    CodePageIn(Vec<u32>),
+    DataPageIn(Vec<u32>),
+    PageDirtied(Vec<u32>),
+    Syscall(String),
+    // the new code pages are reached by a jump or a taken branch at `pc`
+    JumpIntoNewPage { pc: u32, insn: u32, pages: Vec<u32> },
+    Branch { pc: u32, insn: u32, pages: Vec<u32> },
+}
+
+impl CycleCause {
+    pub fn name(&self) -> &'static str {
+        match self {
+            CycleCause::SegmentBoundary => "segment_boundary",
+            CycleCause::CodePageIn(_) => "code_page_in",
+            CycleCause::DataPageIn(_) => "data_page_in",
+            CycleCause::PageDirtied(_) => "page_dirtied",
+            CycleCause::Syscall(_) => "syscall",
+            CycleCause::JumpIntoNewPage { .. } => "jump_into_new_page",
+            CycleCause::Branch { .. } => "branch",
+        }
+    }
+
+    pub fn pages(&self) -> &[u32] {
+        match self {
+            CycleCause::CodePageIn(pages)
+            | CycleCause::DataPageIn(pages)
+            | CycleCause::PageDirtied(pages)
+            | CycleCause::JumpIntoNewPage { pages, .. }
+            | CycleCause::Branch { pages, .. } => pages,
+            CycleCause::SegmentBoundary | CycleCause::Syscall(_) => &[],
+        }
+    }
+}
+
+impl CycleTracer {
+    // for the instruction that has just finished, whose new code pages are the first `num_code_pages` of the accessed pages
+    //
+    // the pages are estimated at `PAGE_CYCLES` each, and what is left goes to the segment boundary, or otherwise to the syscall
+    pub(crate) fn classify_cycles(
+        &self,
+        num_cycles: u32,
+        first_instruction_new_segment: bool,
+        num_code_pages: usize,
+    ) -> Vec<(CycleCause, u32)> {
+        let mut causes = Vec::new();
+
+        let (code_pages, dirtied_pages) = self.latest_accessed_new_pages.split_at(num_code_pages);
+        if !code_pages.is_empty() {
+            let (jmp_pc, jmp_insn) = self.previous_instruction_after_jmp;
+            let (branch_pc, branch_insn) = self.previous_instruction_after_branch;
+            let pages = code_pages.to_vec();
+            causes.push(if jmp_pc != 0 {
+                CycleCause::JumpIntoNewPage {
+                    pc: jmp_pc,
+                    insn: jmp_insn,
+                    pages,
+                }
+            } else if branch_pc != 0 && branch_pc + 4 != self.previous_pc {
+                CycleCause::Branch {
+                    pc: branch_pc,
+                    insn: branch_insn,
+                    pages,
+                }
+            } else {
+                CycleCause::CodePageIn(pages)
+            });
+        }
+        if !self.latest_loaded_pages.is_empty() {
+            causes.push(CycleCause::DataPageIn(self.latest_loaded_pages.clone()));
+        }
+        if !dirtied_pages.is_empty() {
+            causes.push(CycleCause::PageDirtied(dirtied_pages.to_vec()));
+        }
+
+        // a regular instruction takes one cycle
+        let mut remaining = num_cycles.saturating_sub(1);
+        let mut estimated = causes
+            .into_iter()
+            .map(|cause| {
+                let cycles = std::cmp::min(cause.pages().len() as u32 * PAGE_CYCLES, remaining);
+                remaining -= cycles;
+                (cause, cycles)
+            })
+            .collect::<Vec<(CycleCause, u32)>>();
+
+        if first_instruction_new_segment {
+            estimated.push((CycleCause::SegmentBoundary, remaining));
+            remaining = 0;
+        }
+        if let Some(name) = self.previous_syscall.as_ref() {
+            estimated.push((CycleCause::Syscall(name.clone()), remaining));
+        }
+        estimated
+    }
+}
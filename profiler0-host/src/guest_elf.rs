@@ -0,0 +1,72 @@
+use elf::abi::PT_LOAD;
+use elf::endian::LittleEndian;
+use elf::ElfBytes;
+use std::collections::BTreeMap;
+
+pub use elf::ParseError;
+
+pub struct GuestElf {
+    pub segments: Vec<(u32, Vec<u8>)>,
+    // empty if the ELF has been stripped
+    pub symbols: BTreeMap<String, u32>,
+}
+
+impl GuestElf {
+    pub fn parse(elf: &[u8]) -> Result<Self, ParseError> {
+        let file = ElfBytes::<LittleEndian>::minimal_parse(elf)?;
+
+        let mut segments = Vec::new();
+        if let Some(program_headers) = file.segments() {
+            for phdr in program_headers.iter() {
+                if phdr.p_type != PT_LOAD || phdr.p_filesz == 0 {
+                    continue;
+                }
+                let data = file.segment_data(&phdr)?;
+                segments.push((phdr.p_vaddr as u32, data.to_vec()));
+            }
+        }
+
+        let mut symbols = BTreeMap::new();
+        if let Some((symbol_table, string_table)) = file.symbol_table()? {
+            for symbol in symbol_table.iter() {
+                if symbol.st_name == 0 || symbol.is_undefined() {
+                    continue;
+                }
+                let name = string_table.get(symbol.st_name as usize)?;
+                symbols.insert(name.to_string(), symbol.st_value as u32);
+            }
+        }
+
+        Ok(Self { segments, symbols })
+    }
+
+    pub fn symbol(&self, name: &str) -> Option<u32> {
+        self.symbols.get(name).copied()
+    }
+
+    pub fn read(&self, addr: u32, len: u32) -> Option<&[u8]> {
+        for (start, data) in self.segments.iter() {
+            if addr >= *start && (addr as u64 + len as u64) <= (*start as u64 + data.len() as u64) {
+                let offset = (addr - start) as usize;
+                return Some(&data[offset..offset + len as usize]);
+            }
+        }
+        None
+    }
+
+    pub fn read_c_str(&self, addr: u32) -> Option<String> {
+        for (start, data) in self.segments.iter() {
+            if addr >= *start && ((addr - start) as usize) < data.len() {
+                let bytes = &data[(addr - start) as usize..];
+                let len = bytes.iter().position(|b| *b == 0)?;
+                return String::from_utf8(bytes[..len].to_vec()).ok();
+            }
+        }
+        None
+    }
+
+    pub fn read_str(&self, addr: u32, len: u32) -> Option<String> {
+        self.read(addr, len)
+            .and_then(|bytes| String::from_utf8(bytes.to_vec()).ok())
+    }
+}
@@ -0,0 +1,45 @@
+use crate::render::{Renderer, TerminalRenderer};
+use crate::CycleTracer;
+use std::collections::{BTreeSet, HashMap};
+
+pub struct HotspotRecord {
+    pub pc: u32,
+    pub insn: u32,
+    pub count: u32,
+    pub extra_cycles: u64,
+    pub timers: BTreeSet<String>,
+}
+
+impl CycleTracer {
+    pub fn hotspots(&self) -> Vec<HotspotRecord> {
+        let mut hotspots: HashMap<u32, HotspotRecord> = HashMap::new();
+        for significant_cycle in self.significant_cycles.iter() {
+            let hotspot = hotspots
+                .entry(significant_cycle.pc)
+                .or_insert_with(|| HotspotRecord {
+                    pc: significant_cycle.pc,
+                    insn: significant_cycle.insn,
+                    count: 0,
+                    extra_cycles: 0,
+                    timers: BTreeSet::new(),
+                });
+            hotspot.count += 1;
+            // a regular instruction takes one cycle
+            hotspot.extra_cycles +=
+                (significant_cycle.current_cycle - significant_cycle.previous_cycle - 1) as u64;
+            if !hotspot.timers.contains(&significant_cycle.timer_path) {
+                hotspot.timers.insert(significant_cycle.timer_path.clone());
+            }
+        }
+
+        let mut hotspots = hotspots.into_values().collect::<Vec<HotspotRecord>>();
+        hotspots.sort_by_key(|hotspot| (std::cmp::Reverse(hotspot.extra_cycles), hotspot.pc));
+        hotspots
+    }
+
+    pub fn print_hotspots(&self, n: usize) {
+        TerminalRenderer
+            .render_hotspots(self, n, &mut std::io::stdout())
+            .unwrap();
+    }
+}
@@ -0,0 +1,498 @@
+use crate::render::{
+    decode_instruction, format_cycle_cause, format_cycles_per_instruction, format_shares,
+    sorted_page_records, sorted_spans, top_page_timers, Painter,
+};
+use crate::report::{total_cycles, total_instructions, TimerNode};
+use crate::{CycleTracer, InstructionClass, Renderer, ReportOptions, SignificantCycleDisplay};
+use std::io::Write;
+
+// a single page with a timeline, collapsible timers, and sortable tables, which needs no tooling to browse
+pub struct HtmlRenderer;
+
+const STYLE: &str = r#"
+body { font-family: sans-serif; margin: 2em; color: #222; }
+h2 { margin-top: 2em; }
+details { margin-left: 1.5em; }
+summary { cursor: pointer; font-family: monospace; white-space: nowrap; }
+.detail { margin-left: 1.5em; font-family: monospace; color: #555; }
+table { border-collapse: collapse; font-family: monospace; }
+th, td { border: 1px solid #ccc; padding: 2px 8px; text-align: left; }
+th { cursor: pointer; background: #eee; }
+td.num { text-align: right; }
+svg text { font-family: sans-serif; font-size: 11px; pointer-events: none; }
+"#;
+
+// clicking a table header sorts by that column, by number for the cells with `data-value`
+const SCRIPT: &str = r#"
+document.querySelectorAll("table.sortable th").forEach(function (th) {
+  th.addEventListener("click", function () {
+    var column = th.cellIndex;
+    var body = th.closest("table").tBodies[0];
+    var ascending = th.dataset.order !== "asc";
+    th.dataset.order = ascending ? "asc" : "desc";
+    var key = function (row) {
+      var cell = row.cells[column];
+      return cell.dataset.value !== undefined ? parseFloat(cell.dataset.value) : cell.textContent;
+    };
+    Array.from(body.rows).sort(function (a, b) {
+      var x = key(a), y = key(b);
+      var order = x < y ? -1 : x > y ? 1 : 0;
+      return ascending ? order : -order;
+    }).forEach(function (row) { body.appendChild(row); });
+  });
+});
+"#;
+
+const TIMELINE_WIDTH: f64 = 1200.0;
+const TIMELINE_ROW_HEIGHT: u32 = 20;
+const TIMELINE_COLORS: &[&str] = &[
+    "#4e79a7", "#f28e2b", "#59a14f", "#e15759", "#76b7b2", "#b07aa1",
+];
+
+fn escape_html(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn format_addrs(list: &[u32], shift: u32) -> String {
+    let mut sorted = list.to_vec();
+    sorted.sort();
+    sorted
+        .iter()
+        .map(|x| format!("{:#08x}", x << shift))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+fn num_cell(value: impl std::fmt::Display) -> String {
+    format!("<td class=\"num\" data-value=\"{0}\">{0}</td>", value)
+}
+
+fn html_node(
+    tracer: &CycleTracer,
+    node: &TimerNode,
+    parent_cycles: Option<u32>,
+    total_cycles: u64,
+    out: &mut dyn Write,
+) -> std::io::Result<()> {
+    let report = node.record;
+
+    writeln!(out, "<details open>")?;
+    writeln!(
+        out,
+        "<summary><b>{}</b>: {} cycles ({}), {} instructions, {} self cycles, {} cycles per instruction</summary>",
+        escape_html(&report.name),
+        report.num_cycles,
+        format_shares(report.num_cycles, parent_cycles, total_cycles),
+        report.num_instructions,
+        node.self_cycles,
+        format_cycles_per_instruction(report.num_cycles, report.num_instructions),
+    )?;
+
+    if tracer.instrumentation_sequences != 0 {
+        writeln!(
+            out,
+            "<div class=\"detail\">{} cycles, {} instructions without profiler overhead</div>",
+            report.num_cycles - report.instrumentation_cycles,
+            report.num_instructions - report.instrumentation_instructions,
+        )?;
+    }
+
+    let mix = InstructionClass::ALL
+        .iter()
+        .filter(|class| report.instruction_mix.get(**class).0 != 0)
+        .map(|class| {
+            let (num_instructions, num_cycles) = report.instruction_mix.get(*class);
+            format!(
+                "{} {} ({} cycles)",
+                class.name(),
+                num_instructions,
+                num_cycles
+            )
+        })
+        .collect::<Vec<String>>();
+    if !mix.is_empty() {
+        writeln!(
+            out,
+            "<div class=\"detail\">Instructions: {}</div>",
+            escape_html(&mix.join(", "))
+        )?;
+    }
+
+    for (name, syscall) in report.syscalls.iter() {
+        writeln!(
+            out,
+            "<div class=\"detail\">{}: {} calls, {} cycles</div>",
+            escape_html(name),
+            syscall.num_calls,
+            syscall.num_cycles,
+        )?;
+    }
+
+    for child in node.children.iter() {
+        html_node(tracer, child, Some(report.num_cycles), total_cycles, out)?;
+    }
+
+    if report.num_cycles != node.self_cycles && node.self_cycles != 0 {
+        writeln!(
+            out,
+            "<div class=\"detail\">(untracked): {} cycles ({}), {} instructions</div>",
+            node.self_cycles,
+            format_shares(node.self_cycles, Some(report.num_cycles), total_cycles),
+            node.self_instructions,
+        )?;
+    }
+
+    if !node.significant_cycles.is_empty() {
+        writeln!(
+            out,
+            "<div class=\"detail\">{} significant cycles</div>",
+            node.significant_cycles.len()
+        )?;
+    }
+
+    writeln!(out, "</details>")
+}
+
+fn html_significant_cycles(
+    tracer: &CycleTracer,
+    nodes: &[TimerNode],
+    out: &mut dyn Write,
+) -> std::io::Result<()> {
+    // only the significant cycles under the timers that are kept by the report options
+    fn collect(node: &TimerNode, indices: &mut Vec<usize>) {
+        indices.extend(node.significant_cycles.iter());
+        for child in node.children.iter() {
+            collect(child, indices);
+        }
+    }
+    let mut indices = Vec::new();
+    for node in nodes.iter() {
+        collect(node, &mut indices);
+    }
+    indices.sort();
+
+    writeln!(
+        out,
+        "<table class=\"sortable\">\n<thead><tr><th>Cycle</th><th>Cycles</th><th>PC</th><th>Instruction</th><th>Timer</th><th>Writes to</th><th>Causes</th></tr></thead>\n<tbody>"
+    )?;
+    for i in indices.iter() {
+        let significant_cycle = &tracer.significant_cycles[*i];
+
+        let causes = significant_cycle
+            .causes
+            .iter()
+            .map(|(cause, cycles)| {
+                format!(
+                    "{} (~{} cycles)",
+                    format_cycle_cause(&Painter { color: false }, cause),
+                    cycles
+                )
+            })
+            .collect::<Vec<String>>();
+
+        writeln!(
+            out,
+            "<tr>{}{}<td data-value=\"{}\">{:#08x}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            num_cell(significant_cycle.previous_cycle),
+            num_cell(significant_cycle.current_cycle - significant_cycle.previous_cycle),
+            significant_cycle.pc,
+            significant_cycle.pc,
+            escape_html(&decode_instruction(significant_cycle.insn)),
+            escape_html(&significant_cycle.timer_path),
+            format_addrs(&significant_cycle.latest_io_addrs, 0),
+            escape_html(&causes.join(", ")),
+        )?;
+    }
+    writeln!(out, "</tbody>\n</table>")
+}
+
+// one row per nesting level of the timers in every category, and a last row for the segments
+fn html_timeline(tracer: &CycleTracer, out: &mut dyn Write) -> std::io::Result<()> {
+    let end_cycle = tracer.previous_cycle_count.max(1) as f64;
+    let mut first_levels: Vec<(String, u32)> = Vec::new();
+    let mut num_levels = 0;
+    for category in std::iter::once(String::new()).chain(tracer.categories()) {
+        let category_levels = tracer
+            .finished_records
+            .iter()
+            .filter(|record| record.category == category)
+            .map(|record| record.indents + 1)
+            .max()
+            .unwrap_or(0) as u32;
+        first_levels.push((category, num_levels));
+        num_levels += category_levels;
+    }
+    let height = (num_levels + 1) * TIMELINE_ROW_HEIGHT;
+
+    writeln!(
+        out,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\">",
+        TIMELINE_WIDTH, height
+    )?;
+    for (i, record) in tracer.finished_records.iter().enumerate() {
+        let x = record.start_cycle as f64 / end_cycle * TIMELINE_WIDTH;
+        let width = (record.num_cycles as f64 / end_cycle * TIMELINE_WIDTH).max(1.0);
+        let first_level = first_levels
+            .iter()
+            .find(|(category, _)| *category == record.category)
+            .unwrap()
+            .1;
+        let y = (first_level + record.indents as u32) * TIMELINE_ROW_HEIGHT;
+        writeln!(
+            out,
+            "<g><title>{}: cycles {} => {}</title><rect x=\"{:.2}\" y=\"{}\" width=\"{:.2}\" height=\"{}\" fill=\"{}\" stroke=\"#fff\"/>",
+            escape_html(&record.name),
+            record.start_cycle,
+            record.start_cycle + record.num_cycles,
+            x,
+            y,
+            width,
+            TIMELINE_ROW_HEIGHT - 2,
+            TIMELINE_COLORS[i % TIMELINE_COLORS.len()],
+        )?;
+        // only label the bars that are wide enough
+        if width > 8.0 * record.name.len() as f64 {
+            writeln!(
+                out,
+                "<text x=\"{:.2}\" y=\"{}\" fill=\"#fff\">{}</text>",
+                x + 3.0,
+                y + TIMELINE_ROW_HEIGHT - 6,
+                escape_html(&record.name),
+            )?;
+        }
+        writeln!(out, "</g>")?;
+    }
+
+    let y = num_levels * TIMELINE_ROW_HEIGHT;
+    for segment in tracer.segments.iter() {
+        let x = segment.start_cycle as f64 / end_cycle * TIMELINE_WIDTH;
+        let width = ((segment.end_cycle - segment.start_cycle) as f64 / end_cycle * TIMELINE_WIDTH)
+            .max(1.0);
+        writeln!(
+            out,
+            "<g><title>Segment {}: cycles {} => {}</title><rect x=\"{:.2}\" y=\"{}\" width=\"{:.2}\" height=\"{}\" fill=\"#bab0ac\" stroke=\"#fff\"/></g>",
+            segment.index,
+            segment.start_cycle,
+            segment.end_cycle,
+            x,
+            y,
+            width,
+            TIMELINE_ROW_HEIGHT - 2,
+        )?;
+    }
+    writeln!(out, "</svg>")
+}
+
+impl Renderer for HtmlRenderer {
+    // the timeline, the timers of every category, and the significant cycles under the timers that are kept
+    fn render_timers(
+        &self,
+        tracer: &CycleTracer,
+        options: &ReportOptions,
+        out: &mut dyn Write,
+    ) -> std::io::Result<()> {
+        for warning in tracer.warnings.iter() {
+            writeln!(out, "<p><b>Warning</b>: {}</p>", escape_html(warning))?;
+        }
+
+        let roots = tracer.timer_tree();
+        let total_cycles = total_cycles(&roots);
+        let nodes = crate::report::filter_nodes(roots, options, total_cycles, false);
+
+        writeln!(out, "<h2>Timeline</h2>")?;
+        html_timeline(tracer, out)?;
+
+        writeln!(out, "<h2>Timers</h2>")?;
+        for node in nodes.iter() {
+            html_node(tracer, node, None, total_cycles, out)?;
+        }
+        for category in tracer.categories().iter() {
+            let roots = tracer.category_timer_tree(category);
+            let total_cycles = crate::report::total_cycles(&roots);
+            writeln!(
+                out,
+                "<h3>Category {}</h3>\n<p>{} cycles, {} instructions</p>",
+                escape_html(category),
+                total_cycles,
+                total_instructions(&roots),
+            )?;
+            for node in crate::report::filter_nodes(roots, options, total_cycles, false).iter() {
+                html_node(tracer, node, None, total_cycles, out)?;
+            }
+        }
+
+        if options.significant_cycles != SignificantCycleDisplay::Hide {
+            writeln!(out, "<h2>Significant cycles</h2>")?;
+            html_significant_cycles(tracer, &nodes, out)?;
+        }
+        Ok(())
+    }
+
+    fn render_segments(&self, tracer: &CycleTracer, out: &mut dyn Write) -> std::io::Result<()> {
+        writeln!(out, "<h2>Segments</h2>")?;
+        writeln!(
+            out,
+            "<table class=\"sortable\">\n<thead><tr><th>Segment</th><th>Start cycle</th><th>End cycle</th><th>Instructions</th><th>Pages loaded</th><th>Pages dirtied</th><th>Boundary cycles</th><th>Timers</th></tr></thead>\n<tbody>"
+        )?;
+        for segment in tracer.segments.iter() {
+            writeln!(
+                out,
+                "<tr>{}{}{}{}{}{}{}<td>{}</td></tr>",
+                num_cell(segment.index),
+                num_cell(segment.start_cycle),
+                num_cell(segment.end_cycle),
+                num_cell(segment.num_instructions),
+                num_cell(segment.num_pages_loaded),
+                num_cell(segment.num_pages_dirtied),
+                num_cell(segment.boundary_cycles),
+                escape_html(&segment.active_timers.join(", ")),
+            )?;
+        }
+        writeln!(out, "</tbody>\n</table>")
+    }
+
+    fn render_pages(&self, tracer: &CycleTracer, out: &mut dyn Write) -> std::io::Result<()> {
+        writeln!(out, "<h2>Pages</h2>")?;
+        writeln!(
+            out,
+            "<table class=\"sortable\">\n<thead><tr><th>Page</th><th>Segments loaded</th><th>Segments dirtied</th><th>Timers</th></tr></thead>\n<tbody>"
+        )?;
+        for (page_idx, record) in sorted_page_records(tracer).iter() {
+            writeln!(
+                out,
+                "<tr><td data-value=\"{}\">{:#08x}</td>{}{}<td>{}</td></tr>",
+                page_idx << 10,
+                page_idx << 10,
+                num_cell(record.num_segments_loaded),
+                num_cell(record.num_segments_dirtied),
+                escape_html(&top_page_timers(record, 3)),
+            )?;
+        }
+        writeln!(out, "</tbody>\n</table>")
+    }
+
+    // nothing if the guest has no spans
+    fn render_spans(&self, tracer: &CycleTracer, out: &mut dyn Write) -> std::io::Result<()> {
+        if !tracer.finished_spans.is_empty() {
+            writeln!(out, "<h2>Spans</h2>")?;
+            writeln!(
+                out,
+                "<table class=\"sortable\">\n<thead><tr><th>ID</th><th>Span</th><th>Start cycle</th><th>End cycle</th><th>Cycles</th><th>Instructions</th></tr></thead>\n<tbody>"
+            )?;
+            for span in sorted_spans(tracer).iter() {
+                writeln!(
+                    out,
+                    "<tr>{}<td>{}</td>{}{}{}{}</tr>",
+                    num_cell(span.id),
+                    escape_html(&span.name),
+                    num_cell(span.start_cycle),
+                    num_cell(span.start_cycle + span.num_cycles),
+                    num_cell(span.num_cycles),
+                    num_cell(span.num_instructions),
+                )?;
+            }
+            writeln!(out, "</tbody>\n</table>")?;
+        }
+        Ok(())
+    }
+
+    fn render_hotspots(
+        &self,
+        tracer: &CycleTracer,
+        n: usize,
+        out: &mut dyn Write,
+    ) -> std::io::Result<()> {
+        writeln!(out, "<h2>Hotspots</h2>")?;
+        writeln!(
+            out,
+            "<table class=\"sortable\">\n<thead><tr><th>Instruction</th><th>PC</th><th>Extra cycles</th><th>Significant cycles</th><th>Timers</th></tr></thead>\n<tbody>"
+        )?;
+        for hotspot in tracer.hotspots().iter().take(n) {
+            let timers = hotspot
+                .timers
+                .iter()
+                .map(|timer| {
+                    if timer.is_empty() {
+                        "(no timer)"
+                    } else {
+                        timer.as_str()
+                    }
+                })
+                .collect::<Vec<&str>>();
+            writeln!(
+                out,
+                "<tr><td>{}</td><td data-value=\"{}\">{:#08x}</td>{}{}<td>{}</td></tr>",
+                escape_html(&decode_instruction(hotspot.insn)),
+                hotspot.pc,
+                hotspot.pc,
+                num_cell(hotspot.extra_cycles),
+                num_cell(hotspot.count),
+                escape_html(&timers.join(", ")),
+            )?;
+        }
+        writeln!(out, "</tbody>\n</table>")
+    }
+}
+
+impl HtmlRenderer {
+    // the whole page, with every section of the report and all the hotspots
+    pub fn render_document(
+        &self,
+        tracer: &CycleTracer,
+        options: &ReportOptions,
+        out: &mut dyn Write,
+    ) -> std::io::Result<()> {
+        writeln!(out, "<!DOCTYPE html>")?;
+        writeln!(out, "<html>\n<head>\n<meta charset=\"utf-8\">")?;
+        writeln!(out, "<title>RISC Zero cycle profile</title>")?;
+        writeln!(out, "<style>{}</style>\n</head>\n<body>", STYLE)?;
+
+        writeln!(out, "<h1>RISC Zero cycle profile</h1>")?;
+        writeln!(
+            out,
+            "<p>{} cycles, {} instructions, {} segments, {} significant cycles</p>",
+            tracer.previous_cycle_count,
+            tracer.num_instructions,
+            tracer.segments.len(),
+            tracer.significant_cycles.len(),
+        )?;
+        if tracer.instrumentation_sequences != 0 {
+            writeln!(
+                out,
+                "<p>Profiler overhead: {} timer macros take {} cycles, {} instructions</p>",
+                tracer.instrumentation_sequences,
+                tracer.instrumentation_cycles,
+                tracer.instrumentation_instructions,
+            )?;
+        }
+
+        self.render_timers(tracer, options, out)?;
+        self.render_spans(tracer, out)?;
+        self.render_segments(tracer, out)?;
+        self.render_pages(tracer, out)?;
+        self.render_hotspots(tracer, usize::MAX, out)?;
+
+        writeln!(out, "<script>{}</script>\n</body>\n</html>", SCRIPT)
+    }
+}
+
+impl CycleTracer {
+    pub fn write_html<W: Write>(
+        &self,
+        options: &ReportOptions,
+        writer: &mut W,
+    ) -> std::io::Result<()> {
+        HtmlRenderer.render_document(self, options, writer)
+    }
+}
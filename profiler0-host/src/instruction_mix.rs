@@ -0,0 +1,74 @@
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum InstructionClass {
+    Alu,
+    MulDiv,
+    Load,
+    Store,
+    Branch,
+    Jump,
+    Ecall,
+    Other,
+}
+
+impl InstructionClass {
+    pub const ALL: [InstructionClass; 8] = [
+        InstructionClass::Alu,
+        InstructionClass::MulDiv,
+        InstructionClass::Load,
+        InstructionClass::Store,
+        InstructionClass::Branch,
+        InstructionClass::Jump,
+        InstructionClass::Ecall,
+        InstructionClass::Other,
+    ];
+
+    pub fn classify(insn: u32) -> Self {
+        match insn & 0x7f {
+            0b0110011 if (insn >> 25) == 1 => InstructionClass::MulDiv,
+            0b0110011 | 0b0010011 | 0b0110111 | 0b0010111 => InstructionClass::Alu,
+            0b0000011 => InstructionClass::Load,
+            0b0100011 => InstructionClass::Store,
+            0b1100011 => InstructionClass::Branch,
+            0b1101111 | 0b1100111 => InstructionClass::Jump,
+            0b1110011 if insn == 0x00000073 => InstructionClass::Ecall,
+            _ => InstructionClass::Other,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            InstructionClass::Alu => "alu",
+            InstructionClass::MulDiv => "mul/div",
+            InstructionClass::Load => "load",
+            InstructionClass::Store => "store",
+            InstructionClass::Branch => "branch",
+            InstructionClass::Jump => "jump",
+            InstructionClass::Ecall => "ecall",
+            InstructionClass::Other => "other",
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct InstructionMix {
+    pub num_instructions: [u32; 8],
+    pub num_cycles: [u32; 8],
+}
+
+impl InstructionMix {
+    pub fn since(&self, before: &InstructionMix) -> InstructionMix {
+        let mut mix = InstructionMix::default();
+        for i in 0..8 {
+            mix.num_instructions[i] = self.num_instructions[i] - before.num_instructions[i];
+            mix.num_cycles[i] = self.num_cycles[i] - before.num_cycles[i];
+        }
+        mix
+    }
+
+    pub fn get(&self, class: InstructionClass) -> (u32, u32) {
+        (
+            self.num_instructions[class as usize],
+            self.num_cycles[class as usize],
+        )
+    }
+}
@@ -1,26 +1,126 @@
 use risc0_zkvm::TraceEvent;
-use std::collections::{BTreeSet, HashMap};
+use std::collections::BTreeMap;
 
+mod cause;
+pub use cause::{CycleCause, PAGE_CYCLES};
+
+mod page_bitmap;
+pub use page_bitmap::PageBitmap;
+
+mod guest_elf;
+pub use guest_elf::{GuestElf, ParseError};
+
+mod instruction_mix;
+pub use instruction_mix::{InstructionClass, InstructionMix};
+
+mod time_series;
+pub use time_series::{Sample, SampleInterval};
+
+mod hotspots;
+pub use hotspots::HotspotRecord;
+
+mod report;
+pub use report::{NamePattern, ReportOptions, SignificantCycleDisplay, TimerNode};
+
+mod render;
+pub use render::{PlainTextRenderer, Renderer, TerminalRenderer};
+
+mod markdown;
+pub use markdown::MarkdownRenderer;
+
+mod html;
+pub use html::HtmlRenderer;
+
+mod summary;
+pub use summary::{Baseline, BaselineRecord};
+
+mod session;
+pub use session::Session;
+
+mod replay;
+pub use replay::{TraceRecorder, TraceReplayer};
+
+mod stream;
+pub use stream::{ChannelSink, EventSink, ProfileEvent, WriterSink};
+
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+
+// set on the length word when the guest sends the address of a `&'static str` instead of its bytes
+pub const INTERNED_NAME_FLAG: u32 = 0x8000_0000;
+
+// `li x0, 0x5A1` and `li x0, 0x5A0`, which surround the timer macros when the guest turns on `calibrate`
+pub const INSTRUMENTATION_BEGIN_MARKER: u32 = 0x5a100013;
+pub const INSTRUMENTATION_END_MARKER: u32 = 0x5a000013;
+
+pub const ECALL: u32 = 0x00000073;
+
+// separates the category from the name of a timer, for timers that are not in the default category
+pub const CATEGORY_SEPARATOR: char = '\x1f';
+
+// the newest version of the trace protocol that the host understands, which the guest sends at the end of the handshake and
+// keeps in `TRACE_PROTOCOL_VERSION`
+//
+// 1. the message, length, and signal channels, and the span channel for guests that have it; guests before versioning
+// 2. the handshake ends with `li x0, <version>`
+pub const PROTOCOL_VERSION: u32 = 2;
+
+#[derive(Clone)]
 pub struct FinishedRecord {
     pub name: String,
+    pub category: String,
     pub indents: usize,
+    pub start_cycle: u32,
     pub num_instructions: u32,
     pub num_cycles: u32,
+    pub instrumentation_instructions: u32,
+    pub instrumentation_cycles: u32,
+    pub syscalls: BTreeMap<String, SyscallRecord>,
+    pub instruction_mix: InstructionMix,
     pub start_significant_cycles: usize,
     pub end_significant_cycles: usize,
 }
 
 pub struct PendingRecord {
     pub name: String,
+    pub category: String,
     pub num_pending_records: usize,
     pub cur_num_instructions: u32,
     pub cur_num_cycles: u32,
+    pub cur_instrumentation_instructions: u32,
+    pub cur_instrumentation_cycles: u32,
+    pub cur_syscalls: BTreeMap<String, SyscallRecord>,
+    pub cur_instruction_mix: InstructionMix,
     pub start_significant_cycles: usize,
 }
 
+pub struct PendingSpan {
+    pub id: u32,
+    pub name: String,
+    pub cur_num_instructions: u32,
+    pub cur_num_cycles: u32,
+}
+
+pub struct FinishedSpan {
+    pub id: u32,
+    pub name: String,
+    pub start_cycle: u32,
+    pub num_instructions: u32,
+    pub num_cycles: u32,
+}
+
+#[derive(Clone, Default)]
+pub struct SyscallRecord {
+    pub num_calls: u32,
+    pub num_cycles: u32,
+}
+
+#[derive(Clone)]
 pub struct SignificantCycleRecord {
     pub latest_io_addrs: Vec<u32>,
     pub latest_accessed_new_pages: Vec<u32>,
+    pub latest_load_addrs: Vec<u32>,
+    pub latest_loaded_pages: Vec<u32>,
     pub pc: u32,
     pub current_cycle: u32,
     pub insn: u32,
@@ -28,6 +128,29 @@ pub struct SignificantCycleRecord {
     pub previous_instruction_is_jmp: (u32, u32),
     pub previous_instruction_is_branch: (u32, u32),
     pub first_instruction_new_segment: bool,
+    pub syscall: Option<String>,
+    pub timer_path: String,
+    // with the estimated cycles of each
+    pub causes: Vec<(CycleCause, u32)>,
+}
+
+#[derive(Clone)]
+pub struct SegmentRecord {
+    pub index: u32,
+    pub start_cycle: u32,
+    pub end_cycle: u32,
+    pub num_instructions: u32,
+    pub num_pages_loaded: u32,
+    pub num_pages_dirtied: u32,
+    pub boundary_cycles: u32,
+    pub active_timers: Vec<String>,
+}
+
+#[derive(Default)]
+pub struct PageRecord {
+    pub num_segments_loaded: u32,
+    pub num_segments_dirtied: u32,
+    pub timers: BTreeMap<String, u32>,
 }
 
 pub struct CycleTracer {
@@ -35,15 +158,22 @@ pub struct CycleTracer {
     pub trace_msg_channel: u32,
     pub trace_msg_len_channel: u32,
     pub trace_cycle_channel: u32,
+    pub trace_span_channel: u32,
     pub finished_records: Vec<FinishedRecord>,
     pub pending_records: Vec<PendingRecord>,
     pub msg_channel_buffer: [u8; 516],
     pub msg_len_channel_buffer: u32,
+    pub span_id_buffer: u32,
+    pub pending_spans: Vec<PendingSpan>,
+    pub finished_spans: Vec<FinishedSpan>,
     pub num_instructions: u32,
     pub previous_cycle_count: u32,
-    pub page_accessed: BTreeSet<u32>,
+    pub page_accessed: PageBitmap,
+    pub page_loaded: PageBitmap,
     pub latest_io_addrs: Vec<u32>,
     pub latest_accessed_new_pages: Vec<u32>,
+    pub latest_load_addrs: Vec<u32>,
+    pub latest_loaded_pages: Vec<u32>,
     pub significant_cycles: Vec<SignificantCycleRecord>,
     pub previous_pc: u32,
     pub previous_insn: u32,
@@ -51,6 +181,35 @@ pub struct CycleTracer {
     pub previous_instruction_after_jmp: (u32, u32),
     pub previous_instruction_is_branch: (u32, u32),
     pub previous_instruction_after_branch: (u32, u32),
+    pub guest_elf: Option<GuestElf>,
+    pub in_instrumentation: bool,
+    pub previous_instruction_is_instrumentation: bool,
+    pub instrumentation_sequences: u32,
+    pub instrumentation_instructions: u32,
+    pub instrumentation_cycles: u32,
+    pub segments: Vec<SegmentRecord>,
+    pub page_records: BTreeMap<u32, PageRecord>,
+    // the guest registers before the instruction that has just started
+    pub registers: [u32; 32],
+    pub previous_syscall: Option<String>,
+    pub syscalls: BTreeMap<String, SyscallRecord>,
+    pub previous_instruction_class: InstructionClass,
+    pub instruction_mix: InstructionMix,
+    pub num_pages_loaded: u32,
+    pub num_pages_dirtied: u32,
+    pub sample_interval: Option<SampleInterval>,
+    pub next_sample: u32,
+    pub samples: Vec<Sample>,
+    pub session_label: Option<String>,
+    pub sessions: Vec<Session>,
+    pub sink: Option<Box<dyn EventSink>>,
+    // without it, the timers, significant cycles and segments only go to the sink
+    pub retain_records: bool,
+    pub protocol_version: Option<u32>,
+    // problems with the guest, such as a newer protocol, that the reports show first
+    pub warnings: Vec<String>,
+    // `finish` has sent the end of the last segment
+    pub finished: bool,
 }
 
 impl Default for CycleTracer {
@@ -60,15 +219,22 @@ impl Default for CycleTracer {
             trace_msg_channel: 0,
             trace_msg_len_channel: 0,
             trace_cycle_channel: 0,
+            trace_span_channel: 0,
             finished_records: vec![],
             pending_records: vec![],
             msg_channel_buffer: [0u8; 516],
             msg_len_channel_buffer: 0,
+            span_id_buffer: 0,
+            pending_spans: Vec::new(),
+            finished_spans: Vec::new(),
             num_instructions: 0,
             previous_cycle_count: 0,
             latest_io_addrs: Vec::new(),
             latest_accessed_new_pages: Vec::new(),
-            page_accessed: BTreeSet::new(),
+            latest_load_addrs: Vec::new(),
+            latest_loaded_pages: Vec::new(),
+            page_accessed: PageBitmap::default(),
+            page_loaded: PageBitmap::default(),
             significant_cycles: Vec::new(),
             previous_pc: 0,
             previous_insn: 0,
@@ -76,11 +242,259 @@ impl Default for CycleTracer {
             previous_instruction_after_jmp: (0, 0),
             previous_instruction_is_branch: (0, 0),
             previous_instruction_after_branch: (0, 0),
+            guest_elf: None,
+            in_instrumentation: false,
+            previous_instruction_is_instrumentation: false,
+            instrumentation_sequences: 0,
+            instrumentation_instructions: 0,
+            instrumentation_cycles: 0,
+            segments: Vec::new(),
+            page_records: BTreeMap::new(),
+            registers: [0u32; 32],
+            previous_syscall: None,
+            syscalls: BTreeMap::new(),
+            previous_instruction_class: InstructionClass::Other,
+            instruction_mix: InstructionMix::default(),
+            num_pages_loaded: 0,
+            num_pages_dirtied: 0,
+            sample_interval: None,
+            next_sample: 0,
+            samples: Vec::new(),
+            session_label: None,
+            sessions: Vec::new(),
+            sink: None,
+            retain_records: true,
+            protocol_version: None,
+            warnings: Vec::new(),
+            finished: false,
         }
     }
 }
 
 impl CycleTracer {
+    // the channels are also taken from the symbols of the ELF, so the guest does not need to call `init_trace_logger`
+    pub fn load_elf(&mut self, elf: &[u8]) -> Result<(), ParseError> {
+        self.guest_elf = Some(GuestElf::parse(elf)?);
+        self.find_channels();
+        Ok(())
+    }
+
+    // returns false, and waits for the handshake, if the ELF is stripped or does not use the profiler
+    pub fn find_channels(&mut self) -> bool {
+        // the channels of another guest must not be kept
+        self.trace_msg_channel = 0;
+        self.trace_msg_len_channel = 0;
+        self.trace_cycle_channel = 0;
+        self.trace_span_channel = 0;
+        self.protocol_version = None;
+        self.init_state_machine = 0;
+
+        let elf = match self.guest_elf.as_ref() {
+            Some(elf) => elf,
+            None => return false,
+        };
+        let (msg, msg_len, signal) = match (
+            elf.symbol("TRACE_MSG_CHANNEL"),
+            elf.symbol("TRACE_MSG_LEN_CHANNEL"),
+            elf.symbol("TRACE_SIGNAL_CHANNEL"),
+        ) {
+            (Some(msg), Some(msg_len), Some(signal)) => (msg, msg_len, signal),
+            _ => return false,
+        };
+        self.trace_msg_channel = msg;
+        self.trace_msg_len_channel = msg_len;
+        self.trace_cycle_channel = signal;
+        // older guests do not have the span channel
+        self.trace_span_channel = elf.symbol("TRACE_SPAN_CHANNEL").unwrap_or(0);
+        let version = elf
+            .symbol("TRACE_PROTOCOL_VERSION")
+            .and_then(|addr| elf.read(addr, 4))
+            .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+            .unwrap_or(1);
+        self.init_state_machine = 999;
+        self.set_protocol_version(version);
+        true
+    }
+
+    fn set_protocol_version(&mut self, version: u32) {
+        self.protocol_version = Some(version);
+        if version > PROTOCOL_VERSION {
+            self.warnings.push(format!(
+                "the guest uses version {} of the trace protocol, but this host only understands up to version {}, \
+                so the timers may be wrong; use the same versions of l2r0-profiler-guest and l2r0-profiler-host",
+                version, PROTOCOL_VERSION
+            ));
+        }
+    }
+
+    // a mistake of the guest may repeat in a loop, but it only needs to be reported once
+    fn warn_once(&mut self, warning: String) {
+        if !self.warnings.contains(&warning) {
+            self.warnings.push(warning);
+        }
+    }
+
+    fn read_timer_name(&self, value: u32) -> String {
+        if value & INTERNED_NAME_FLAG == 0 {
+            // a guest with a different layout may send anything
+            return match self.msg_channel_buffer.get(0..value as usize) {
+                Some(bytes) => String::from_utf8_lossy(bytes).to_string(),
+                None => format!("<name of {} bytes>", value),
+            };
+        }
+
+        // the message channel only holds the address of the name, which lives in the guest's read-only data
+        let addr = u32::from_le_bytes(self.msg_channel_buffer[0..4].try_into().unwrap());
+        let len = value & !INTERNED_NAME_FLAG;
+        self.guest_elf
+            .as_ref()
+            .and_then(|elf| elf.read_str(addr, len))
+            .unwrap_or_else(|| format!("<name at {:#08x}>", addr))
+    }
+
+    fn access_page(&mut self, addr: u32, is_write: bool) {
+        let mut page_idx = addr >> 10;
+        while self.page_accessed.insert(page_idx) {
+            self.latest_accessed_new_pages.push(page_idx);
+            // pages that have been read before are already loaded
+            let is_new = !self.page_loaded.contains(page_idx);
+            self.record_page(page_idx, is_new, is_write);
+            page_idx = (0x0D00_0000 + page_idx * 32) >> 10;
+        }
+    }
+
+    fn load_page(&mut self, addr: u32) {
+        let mut page_idx = addr >> 10;
+        while !self.page_accessed.contains(page_idx) && self.page_loaded.insert(page_idx) {
+            self.latest_loaded_pages.push(page_idx);
+            self.record_page(page_idx, true, false);
+            page_idx = (0x0D00_0000 + page_idx * 32) >> 10;
+        }
+    }
+
+    fn record_page(&mut self, page_idx: u32, is_new: bool, is_write: bool) {
+        if is_new {
+            self.num_pages_loaded += 1;
+        }
+        if is_write {
+            self.num_pages_dirtied += 1;
+        }
+
+        if let Some(segment) = self.segments.last_mut() {
+            if is_new {
+                segment.num_pages_loaded += 1;
+            }
+            if is_write {
+                segment.num_pages_dirtied += 1;
+            }
+        }
+
+        let page_record = self.page_records.entry(page_idx).or_default();
+        if is_new {
+            page_record.num_segments_loaded += 1;
+        }
+        if is_write {
+            page_record.num_segments_dirtied += 1;
+        }
+        let timer = match self.pending_records.last() {
+            Some(record) => record.name.as_str(),
+            None => "(no timer)",
+        };
+        match page_record.timers.get_mut(timer) {
+            Some(count) => *count += 1,
+            None => {
+                page_record.timers.insert(timer.to_string(), 1);
+            }
+        }
+    }
+
+    fn start_segment(&mut self, cycle: u32) {
+        // before the previous segment is taken out when the records are not retained
+        let boundary_cycles = if self.segments.is_empty() {
+            0
+        } else {
+            (cycle - self.previous_cycle_count).saturating_sub(1)
+        };
+
+        if !self.retain_records {
+            if let Some(segment) = self.segments.pop() {
+                self.emit(ProfileEvent::SegmentEnd(segment));
+            }
+        } else if self.sink.is_some() {
+            if let Some(segment) = self.segments.last() {
+                let segment = segment.clone();
+                self.emit(ProfileEvent::SegmentEnd(segment));
+            }
+        }
+
+        self.segments.push(SegmentRecord {
+            index: cycle >> 20,
+            start_cycle: cycle,
+            end_cycle: cycle,
+            num_instructions: 0,
+            num_pages_loaded: 0,
+            num_pages_dirtied: 0,
+            boundary_cycles,
+            active_timers: self
+                .pending_records
+                .iter()
+                .map(|record| record.name.clone())
+                .collect(),
+        });
+        self.emit(ProfileEvent::SegmentStart {
+            index: cycle >> 20,
+            cycle,
+        });
+    }
+
+    pub fn current_timer_path(&self) -> String {
+        self.pending_records
+            .iter()
+            .map(|record| record.name.as_str())
+            .collect::<Vec<&str>>()
+            .join("/")
+    }
+
+    fn take_sample(&mut self, cycle: u32) {
+        let (position, interval) = match self.sample_interval {
+            Some(SampleInterval::Instructions(n)) => (self.num_instructions, n.max(1)),
+            Some(SampleInterval::Cycles(n)) => (cycle, n.max(1)),
+            None => return,
+        };
+        if position < self.next_sample {
+            return;
+        }
+
+        self.samples.push(Sample {
+            cycle,
+            num_instructions: self.num_instructions,
+            num_pages_loaded: self.num_pages_loaded,
+            num_pages_dirtied: self.num_pages_dirtied,
+            timer_path: self.current_timer_path(),
+        });
+
+        // a single instruction may take more cycles than the interval
+        self.next_sample = (position / interval + 1) * interval;
+    }
+
+    fn decode_syscall(&self) -> String {
+        // the kind of the ecall is in t0, and a software syscall has its name in a2
+        match self.registers[5] {
+            0 => "sys_halt".to_string(),
+            1 => "sys_input".to_string(),
+            2 => {
+                let addr = self.registers[12];
+                match self.guest_elf.as_ref().and_then(|elf| elf.read_c_str(addr)) {
+                    Some(name) => name.rsplit("::").next().unwrap().to_lowercase(),
+                    None => format!("sys_software at {:#08x}", addr),
+                }
+            }
+            3 => "sys_sha".to_string(),
+            4 => "sys_bigint".to_string(),
+            t0 => format!("ecall {}", t0),
+        }
+    }
+
     pub fn handle_event(&mut self, event: TraceEvent) {
         match event {
             TraceEvent::InstructionStart { cycle, pc, insn } => {
@@ -88,29 +502,43 @@ impl CycleTracer {
                 if (cycle >> 20) != (self.previous_cycle_count >> 20) {
                     // a new segment has started
                     self.page_accessed.clear();
+                    self.page_loaded.clear();
                     is_new_segment = true;
                 }
+                if is_new_segment || self.segments.is_empty() {
+                    self.start_segment(cycle);
+                }
 
-                let mut page_idx = self.previous_pc >> 10;
-                while !self.page_accessed.contains(&page_idx) {
-                    self.page_accessed.insert(page_idx);
-                    self.latest_accessed_new_pages.push(page_idx);
-                    page_idx = (0x0D00_0000 + page_idx * 32) >> 10;
+                self.access_page(self.previous_pc, false);
+                let num_code_pages = self.latest_accessed_new_pages.len();
+                for i in 0..self.latest_load_addrs.len() {
+                    self.load_page(self.latest_load_addrs[i]);
+                }
+                for i in 0..self.latest_io_addrs.len() {
+                    self.access_page(self.latest_io_addrs[i], true);
                 }
 
-                for addr in self.latest_io_addrs.iter() {
-                    let mut page_idx = addr >> 10;
-                    while !self.page_accessed.contains(&page_idx) {
-                        self.page_accessed.insert(page_idx);
-                        self.latest_accessed_new_pages.push(page_idx);
-                        page_idx = (0x0D00_0000 + page_idx * 32) >> 10;
-                    }
+                if self.previous_instruction_is_instrumentation {
+                    self.instrumentation_cycles += cycle - self.previous_cycle_count;
+                }
+
+                if self.num_instructions != 0 {
+                    self.instruction_mix.num_cycles[self.previous_instruction_class as usize] +=
+                        cycle - self.previous_cycle_count;
                 }
 
-                if cycle - self.previous_cycle_count >= 1094 {
-                    self.significant_cycles.push(SignificantCycleRecord {
+                if let Some(name) = self.previous_syscall.as_ref() {
+                    let syscall = self.syscalls.entry(name.clone()).or_default();
+                    syscall.num_calls += 1;
+                    syscall.num_cycles += cycle - self.previous_cycle_count;
+                }
+
+                if cycle - self.previous_cycle_count >= PAGE_CYCLES {
+                    let significant_cycle = SignificantCycleRecord {
                         latest_io_addrs: self.latest_io_addrs.clone(),
                         latest_accessed_new_pages: self.latest_accessed_new_pages.clone(),
+                        latest_load_addrs: self.latest_load_addrs.clone(),
+                        latest_loaded_pages: self.latest_loaded_pages.clone(),
                         pc: self.previous_pc,
                         current_cycle: cycle,
                         insn: self.previous_insn,
@@ -118,11 +546,28 @@ impl CycleTracer {
                         previous_instruction_is_jmp: self.previous_instruction_after_jmp,
                         previous_instruction_is_branch: self.previous_instruction_after_branch,
                         first_instruction_new_segment: is_new_segment,
-                    });
+                        syscall: self.previous_syscall.clone(),
+                        timer_path: self.current_timer_path(),
+                        causes: self.classify_cycles(
+                            cycle - self.previous_cycle_count,
+                            is_new_segment,
+                            num_code_pages,
+                        ),
+                    };
+                    if self.retain_records {
+                        if self.sink.is_some() {
+                            self.emit(ProfileEvent::SignificantCycle(significant_cycle.clone()));
+                        }
+                        self.significant_cycles.push(significant_cycle);
+                    } else {
+                        self.emit(ProfileEvent::SignificantCycle(significant_cycle));
+                    }
                 }
 
                 self.latest_io_addrs.clear();
                 self.latest_accessed_new_pages.clear();
+                self.latest_load_addrs.clear();
+                self.latest_loaded_pages.clear();
 
                 self.previous_pc = pc;
                 self.previous_insn = insn;
@@ -131,6 +576,24 @@ impl CycleTracer {
                 self.previous_instruction_after_branch = self.previous_instruction_is_branch;
                 self.num_instructions += 1;
 
+                let segment = self.segments.last_mut().unwrap();
+                segment.end_cycle = cycle;
+                segment.num_instructions += 1;
+
+                self.take_sample(cycle);
+
+                if insn == INSTRUMENTATION_BEGIN_MARKER {
+                    self.in_instrumentation = true;
+                }
+                self.previous_instruction_is_instrumentation = self.in_instrumentation;
+                if self.in_instrumentation {
+                    self.instrumentation_instructions += 1;
+                }
+                if insn == INSTRUMENTATION_END_MARKER && self.in_instrumentation {
+                    self.in_instrumentation = false;
+                    self.instrumentation_sequences += 1;
+                }
+
                 if (insn & 0x7f == 0x6f) || (insn & 0x7f == 0x67) {
                     self.previous_instruction_is_jmp = (pc, insn);
                 } else {
@@ -143,14 +606,39 @@ impl CycleTracer {
                     self.previous_instruction_is_branch = (0, 0);
                 }
 
+                self.previous_instruction_class = InstructionClass::classify(insn);
+                self.instruction_mix.num_instructions[self.previous_instruction_class as usize] +=
+                    1;
+
+                if insn == ECALL {
+                    self.previous_syscall = Some(self.decode_syscall());
+                } else {
+                    self.previous_syscall = None;
+                }
+
+                // lb, lh, lw, lbu, lhu: the registers have not been changed by this instruction yet
+                if insn & 0x7f == 0b0000011 && matches!((insn >> 12) & 0x7, 0 | 1 | 2 | 4 | 5) {
+                    let rs1 = ((insn >> 15) & 0x1f) as usize;
+                    let imm = (insn as i32) >> 20;
+                    self.latest_load_addrs
+                        .push(self.registers[rs1].wrapping_add(imm as u32));
+                }
+
                 if self.init_state_machine == 999 {
                     return;
                 }
 
                 if insn == 0x00000013 {
                     // nop
-                    if self.init_state_machine == 0 {
+                    // padding before the sequence may also be nops
+                    if self.init_state_machine == 0 || self.init_state_machine == 1 {
                         self.init_state_machine = 1;
+                    } else if self.init_state_machine == 9 || self.init_state_machine == 11 {
+                        // older guests do not have the span channel or the version
+                        self.set_protocol_version(1);
+                        self.init_state_machine = 999;
+                    } else if self.init_state_machine == 12 {
+                        self.init_state_machine = 999;
                     } else {
                         self.init_state_machine = 0;
                     }
@@ -171,14 +659,17 @@ impl CycleTracer {
                 } else if insn & 0x00000fff == 0x017 {
                     // auipc zero, ??? (12 bits)
                     if self.init_state_machine == 3 {
-                        self.trace_msg_channel = pc + (insn & 0xfffff000);
+                        self.trace_msg_channel = pc.wrapping_add(insn & 0xfffff000);
                         self.init_state_machine = 4;
                     } else if self.init_state_machine == 5 {
-                        self.trace_msg_len_channel = pc + (insn & 0xfffff000);
+                        self.trace_msg_len_channel = pc.wrapping_add(insn & 0xfffff000);
                         self.init_state_machine = 6;
                     } else if self.init_state_machine == 7 {
-                        self.trace_cycle_channel = pc + (insn & 0xfffff000);
+                        self.trace_cycle_channel = pc.wrapping_add(insn & 0xfffff000);
                         self.init_state_machine = 8;
+                    } else if self.init_state_machine == 9 {
+                        self.trace_span_channel = pc.wrapping_add(insn & 0xfffff000);
+                        self.init_state_machine = 10;
                     } else {
                         self.init_state_machine = 0;
                     }
@@ -207,14 +698,26 @@ impl CycleTracer {
                         } else {
                             self.trace_cycle_channel += abs;
                         }
-
-                        self.init_state_machine = 999
+                        self.init_state_machine = 9;
+                    } else if self.init_state_machine == 10 {
+                        if neg {
+                            self.trace_span_channel -= 4096 - abs;
+                        } else {
+                            self.trace_span_channel += abs;
+                        }
+                        self.init_state_machine = 11;
+                    } else if self.init_state_machine == 11 && !neg {
+                        // li zero, <version>
+                        self.set_protocol_version(abs);
+                        self.init_state_machine = 12;
                     } else {
                         self.init_state_machine = 0;
                     }
                 }
             }
-            TraceEvent::RegisterSet { .. } => {}
+            TraceEvent::RegisterSet { idx, value } => {
+                self.registers[idx] = value;
+            }
             TraceEvent::MemorySet { addr, region } => {
                 self.latest_io_addrs.push(addr);
 
@@ -227,252 +730,168 @@ impl CycleTracer {
                         + ((region[1] as u32) << 8)
                         + ((region[2] as u32) << 16)
                         + ((region[3] as u32) << 24);
-                    let str =
-                        String::from_utf8(self.msg_channel_buffer[0..value as usize].to_vec())
-                            .unwrap();
+                    let str = self.read_timer_name(value);
+                    let (category, name) = match str.split_once(CATEGORY_SEPARATOR) {
+                        Some((category, name)) => (category.to_string(), name.to_string()),
+                        None => (String::new(), str),
+                    };
+                    if let Some(segment) = self.segments.last_mut() {
+                        if !segment.active_timers.contains(&name) {
+                            segment.active_timers.push(name.clone());
+                        }
+                    }
+                    // every category has its own nesting
+                    let num_pending_records = self
+                        .pending_records
+                        .iter()
+                        .filter(|record| record.category == category)
+                        .count();
                     self.pending_records.push(PendingRecord {
-                        name: str,
-                        num_pending_records: self.pending_records.len(),
+                        name,
+                        category,
+                        num_pending_records,
                         cur_num_instructions: self.num_instructions,
                         cur_num_cycles: self.previous_cycle_count,
+                        cur_instrumentation_instructions: self.instrumentation_instructions,
+                        cur_instrumentation_cycles: self.instrumentation_cycles,
+                        cur_syscalls: self.syscalls.clone(),
+                        cur_instruction_mix: self.instruction_mix.clone(),
                         start_significant_cycles: self.significant_cycles.len(),
                     });
+                    if self.sink.is_some() {
+                        let record = self.pending_records.last().unwrap();
+                        let event = ProfileEvent::TimerStart {
+                            name: record.name.clone(),
+                            category: record.category.clone(),
+                            depth: record.num_pending_records,
+                            cycle: record.cur_num_cycles,
+                        };
+                        self.emit(event);
+                    }
                 }
                 if addr == self.trace_cycle_channel {
-                    let elem = self.pending_records.pop().unwrap();
-                    self.finished_records.push(FinishedRecord {
+                    // zero stops a timer in the default category, otherwise the category is sent like a name
+                    let value = u32::from_le_bytes(region[0..4].try_into().unwrap());
+                    let category = if value == 0 {
+                        String::new()
+                    } else {
+                        self.read_timer_name(value)
+                    };
+                    // a guest may misspell the category, and the other channels are at other addresses
+                    let i = match self
+                        .pending_records
+                        .iter()
+                        .rposition(|record| record.category == category)
+                    {
+                        Some(i) => i,
+                        None => {
+                            self.warn_once(if category.is_empty() {
+                                "the guest stops a timer when none is open, so the stop is ignored"
+                                    .to_string()
+                            } else {
+                                format!(
+                                    "the guest stops a timer of category \"{}\" when none is open, so the stop is ignored",
+                                    category
+                                )
+                            });
+                            return;
+                        }
+                    };
+                    let elem = self.pending_records.remove(i);
+                    let record = FinishedRecord {
                         name: elem.name,
+                        category: elem.category,
                         indents: elem.num_pending_records,
+                        start_cycle: elem.cur_num_cycles,
                         num_instructions: self.num_instructions - elem.cur_num_instructions,
                         num_cycles: self.previous_cycle_count - elem.cur_num_cycles,
+                        instrumentation_instructions: self.instrumentation_instructions
+                            - elem.cur_instrumentation_instructions,
+                        instrumentation_cycles: self.instrumentation_cycles
+                            - elem.cur_instrumentation_cycles,
+                        syscalls: self
+                            .syscalls
+                            .iter()
+                            .filter_map(|(name, syscall)| {
+                                let before =
+                                    elem.cur_syscalls.get(name).cloned().unwrap_or_default();
+                                if syscall.num_calls == before.num_calls {
+                                    None
+                                } else {
+                                    Some((
+                                        name.clone(),
+                                        SyscallRecord {
+                                            num_calls: syscall.num_calls - before.num_calls,
+                                            num_cycles: syscall.num_cycles - before.num_cycles,
+                                        },
+                                    ))
+                                }
+                            })
+                            .collect(),
+                        instruction_mix: self.instruction_mix.since(&elem.cur_instruction_mix),
                         start_significant_cycles: elem.start_significant_cycles,
                         end_significant_cycles: self.significant_cycles.len(),
-                    });
+                    };
+                    if self.retain_records {
+                        if self.sink.is_some() {
+                            self.emit(ProfileEvent::TimerStop(record.clone()));
+                        }
+                        self.finished_records.push(record);
+                    } else {
+                        self.emit(ProfileEvent::TimerStop(record));
+                    }
+                }
+                if self.trace_span_channel != 0 {
+                    if addr == self.trace_span_channel {
+                        self.span_id_buffer = u32::from_le_bytes(region[0..4].try_into().unwrap());
+                    }
+                    if addr == self.trace_span_channel + 4 {
+                        let value = u32::from_le_bytes(region[0..4].try_into().unwrap());
+                        self.pending_spans.push(PendingSpan {
+                            id: self.span_id_buffer,
+                            name: self.read_timer_name(value),
+                            cur_num_instructions: self.num_instructions,
+                            cur_num_cycles: self.previous_cycle_count,
+                        });
+                    }
+                    if addr == self.trace_span_channel + 8 {
+                        // spans are not nested, so the stopped one can be anywhere among the open ones
+                        let id = u32::from_le_bytes(region[0..4].try_into().unwrap());
+                        if let Some(i) = self.pending_spans.iter().rposition(|span| span.id == id) {
+                            let span = self.pending_spans.remove(i);
+                            self.finished_spans.push(FinishedSpan {
+                                id,
+                                name: span.name,
+                                start_cycle: span.cur_num_cycles,
+                                num_instructions: self.num_instructions - span.cur_num_instructions,
+                                num_cycles: self.previous_cycle_count - span.cur_num_cycles,
+                            });
+                        }
+                    }
                 }
             }
         }
     }
 
     pub fn print(&self) {
-        use colored::Colorize;
-
-        fn compute_indent(indent_amount: usize) -> String {
-            let mut indent = String::new();
-            for _ in 0..indent_amount {
-                indent.push_str(&"····");
-            }
-            if indent_amount != 0 {
-                indent.push_str(" ");
-            }
-            indent
-        }
-
-        let mut significant_cycles_shown = vec![false; self.significant_cycles.len()];
-
-        let mut output: HashMap<usize, String> = HashMap::new();
-        let mut cur_level = 0;
-
-        for report in self.finished_records.iter() {
-            let mut cur_string = if report.indents >= cur_level {
-                cur_level = report.indents;
-                let mut cur_string = output.get(&cur_level).cloned().unwrap_or_default();
-                cur_string += &format!(
-                    "{}{}: {} cycles, {} instructions\n",
-                    compute_indent(cur_level),
-                    report.name,
-                    format!("{}", report.num_cycles).blue(),
-                    format!("{}", report.num_instructions).blue(),
-                );
-                cur_string
-            } else if report.indents < cur_level {
-                let tmp_string = output.get(&cur_level).cloned().unwrap_or_default();
-                output.insert(cur_level, "".to_string());
-
-                cur_level = report.indents;
-
-                let mut cur_string = output.get(&cur_level).cloned().unwrap_or_default();
-                cur_string += &format!(
-                    "{}{}: {} cycles, {} instructions\n{}",
-                    compute_indent(cur_level),
-                    report.name,
-                    format!("{}", report.num_cycles).blue(),
-                    format!("{}", report.num_instructions).blue(),
-                    tmp_string
-                );
-                cur_string
-            } else {
-                unimplemented!()
-            };
-
-            if report.end_significant_cycles != report.start_significant_cycles {
-                for i in report.start_significant_cycles..report.end_significant_cycles {
-                    if significant_cycles_shown[i] == false {
-                        let significant_cycle = &self.significant_cycles[i];
-
-                        let addr_string = if significant_cycle.latest_io_addrs.is_empty() {
-                            "".to_string()
-                        } else {
-                            if significant_cycle.latest_io_addrs.len() > 4 {
-                                let mut sorted = significant_cycle.latest_io_addrs.clone();
-                                sorted.sort();
-
-                                let str = sorted
-                                    .iter()
-                                    .take(4)
-                                    .map(|x| format!("{:#08x}", x).to_string())
-                                    .collect::<Vec<String>>();
-                                let last = format!("{:#08x}", sorted[sorted.len() - 1]);
-                                format!(
-                                    " writes to {}, ..., {}",
-                                    str.join(", ").white(),
-                                    last.white()
-                                )
-                            } else {
-                                let mut sorted = significant_cycle.latest_io_addrs.clone();
-                                sorted.sort();
-
-                                let str = sorted
-                                    .iter()
-                                    .map(|x| format!("{:#08x}", x).to_string())
-                                    .collect::<Vec<String>>();
-                                format!(" writes to {}", str.join(", ").white())
-                            }
-                        };
-
-                        let page_string = if significant_cycle.latest_accessed_new_pages.is_empty()
-                        {
-                            "".to_string()
-                        } else {
-                            if significant_cycle.latest_accessed_new_pages.len() > 4 {
-                                let mut sorted =
-                                    significant_cycle.latest_accessed_new_pages.clone();
-                                sorted.sort();
-
-                                let str = sorted
-                                    .iter()
-                                    .take(4)
-                                    .map(|x| format!("{:#08x}", x << 10).to_string())
-                                    .collect::<Vec<String>>();
-                                let last = format!("{:#08x}", sorted[sorted.len() - 1] << 10,);
-                                format!(
-                                    " marks pages {}, ..., {} as dirty",
-                                    str.join(", ").white(),
-                                    last.white()
-                                )
-                            } else {
-                                let mut sorted =
-                                    significant_cycle.latest_accessed_new_pages.clone();
-                                sorted.sort();
-
-                                let str = sorted
-                                    .iter()
-                                    .map(|x| format!("{:#08x}", x << 10).to_string())
-                                    .collect::<Vec<String>>();
-                                format!(" marks pages {} as dirty", str.join(", ").white())
-                            }
-                        };
-
-                        let prefix_word = if !significant_cycle.latest_accessed_new_pages.is_empty()
-                            || !significant_cycle.latest_io_addrs.is_empty()
-                        {
-                            " that".to_string()
-                        } else {
-                            "".to_string()
-                        };
-
-                        let glue_word = if !significant_cycle.latest_accessed_new_pages.is_empty()
-                            && !significant_cycle.latest_io_addrs.is_empty()
-                        {
-                            " and".to_string()
-                        } else {
-                            "".to_string()
-                        };
-
-                        let first_insn_word = if significant_cycle.first_instruction_new_segment {
-                            " (first instruction in the new segment)".to_string()
-                        } else {
-                            "".to_string()
-                        };
-
-                        use raki::decode::Decode;
-                        use raki::Isa;
-
-                        let jump_string = if significant_cycle.previous_instruction_is_jmp.0 == 0 {
-                            "".to_string()
-                        } else {
-                            format!(
-                                ", due to {} at {},",
-                                format!(
-                                    "{}",
-                                    significant_cycle
-                                        .previous_instruction_is_jmp
-                                        .1
-                                        .decode(Isa::Rv32)
-                                        .unwrap()
-                                )
-                                .blue(),
-                                format!("{:#08x}", significant_cycle.previous_instruction_is_jmp.0)
-                                    .white(),
-                            )
-                        };
-
-                        let branch_string = if significant_cycle.previous_instruction_is_branch.0
-                            == 0
-                            || significant_cycle.previous_instruction_is_branch.0 + 4
-                                == significant_cycle.pc
-                        {
-                            "".to_string()
-                        } else {
-                            format!(
-                                ", due to {} at {},",
-                                format!(
-                                    "{}",
-                                    significant_cycle
-                                        .previous_instruction_is_branch
-                                        .1
-                                        .decode(Isa::Rv32)
-                                        .unwrap()
-                                )
-                                .blue(),
-                                format!(
-                                    "{:#08x}",
-                                    significant_cycle.previous_instruction_is_branch.0
-                                )
-                                .white(),
-                            )
-                        };
+        self.print_with(&ReportOptions::default());
+    }
 
-                        let decode =
-                            format!("{}", significant_cycle.insn.decode(Isa::Rv32).unwrap());
-
-                        cur_string += &format!(
-                            "{}Cycle: {} => {}: {} at {}{}{}{}{}{}{}{} takes {} cycles\n",
-                            compute_indent(cur_level + 1),
-                            significant_cycle.previous_cycle,
-                            significant_cycle.current_cycle,
-                            decode.blue(),
-                            format!("{:#08x}", significant_cycle.pc).white(),
-                            first_insn_word,
-                            jump_string,
-                            branch_string,
-                            prefix_word,
-                            addr_string,
-                            glue_word,
-                            page_string,
-                            format!(
-                                "{}",
-                                significant_cycle.current_cycle - significant_cycle.previous_cycle
-                            )
-                            .blue(),
-                        );
-                    }
-                    significant_cycles_shown[i] = true;
-                }
-            }
+    pub fn print_segments(&self) {
+        TerminalRenderer
+            .render_segments(self, &mut std::io::stdout())
+            .unwrap();
+    }
 
-            output.insert(cur_level, cur_string);
-        }
+    pub fn print_spans(&self) {
+        TerminalRenderer
+            .render_spans(self, &mut std::io::stdout())
+            .unwrap();
+    }
 
-        println!("{}", output.get(&0).cloned().unwrap_or_default().green());
+    pub fn print_pages(&self) {
+        TerminalRenderer
+            .render_pages(self, &mut std::io::stdout())
+            .unwrap();
     }
 }
@@ -0,0 +1,285 @@
+use crate::render::{
+    decode_instruction, format_cycles_per_instruction, format_shares, format_significant_cycle,
+    page_map_lines, significant_cycles_total, sorted_page_records, sorted_spans, top_page_timers,
+    Painter, Renderer,
+};
+use crate::report::{total_cycles, total_instructions, TimerNode};
+use crate::{CycleTracer, InstructionClass, ReportOptions, SignificantCycleDisplay};
+use std::io::Write;
+
+// nested lists for the timers and tables for everything else, for files and pull-request comments
+pub struct MarkdownRenderer;
+
+fn escape_cell(s: &str) -> String {
+    s.replace('|', "\\|")
+}
+
+fn markdown_node(
+    tracer: &CycleTracer,
+    node: &TimerNode,
+    parent_cycles: Option<u32>,
+    total_cycles: u64,
+    options: &ReportOptions,
+    out: &mut dyn Write,
+) -> std::io::Result<()> {
+    let painter = Painter { color: false };
+    let report = node.record;
+    let indent = "  ".repeat(node.depth);
+    let inner = "  ".repeat(node.depth + 1);
+
+    let overhead = if tracer.instrumentation_sequences == 0 {
+        "".to_string()
+    } else {
+        format!(
+            " ({} cycles, {} instructions without profiler overhead)",
+            report.num_cycles - report.instrumentation_cycles,
+            report.num_instructions - report.instrumentation_instructions,
+        )
+    };
+
+    writeln!(
+        out,
+        "{}- **{}**: {} cycles ({}), {} instructions, {} self cycles, {} cycles per instruction{}",
+        indent,
+        report.name,
+        report.num_cycles,
+        format_shares(report.num_cycles, parent_cycles, total_cycles),
+        report.num_instructions,
+        node.self_cycles,
+        format_cycles_per_instruction(report.num_cycles, report.num_instructions),
+        overhead,
+    )?;
+
+    let mix = InstructionClass::ALL
+        .iter()
+        .filter(|class| report.instruction_mix.get(**class).0 != 0)
+        .map(|class| {
+            let (num_instructions, num_cycles) = report.instruction_mix.get(*class);
+            format!(
+                "{} {} ({} cycles)",
+                class.name(),
+                num_instructions,
+                num_cycles
+            )
+        })
+        .collect::<Vec<String>>();
+    if !mix.is_empty() {
+        writeln!(out, "{}- Instructions: {}", inner, mix.join(", "))?;
+    }
+
+    for (name, syscall) in report.syscalls.iter() {
+        writeln!(
+            out,
+            "{}- `{}`: {} calls, {} cycles",
+            inner, name, syscall.num_calls, syscall.num_cycles
+        )?;
+    }
+
+    for child in node.children.iter() {
+        markdown_node(
+            tracer,
+            child,
+            Some(report.num_cycles),
+            total_cycles,
+            options,
+            out,
+        )?;
+    }
+
+    if report.num_cycles != node.self_cycles && node.self_cycles != 0 {
+        writeln!(
+            out,
+            "{}- *(untracked)*: {} cycles ({}), {} instructions",
+            inner,
+            node.self_cycles,
+            format_shares(node.self_cycles, Some(report.num_cycles), total_cycles),
+            node.self_instructions,
+        )?;
+    }
+
+    match options.significant_cycles {
+        SignificantCycleDisplay::Show => {
+            for i in node.significant_cycles.iter() {
+                writeln!(
+                    out,
+                    "{}- {}",
+                    inner,
+                    format_significant_cycle(&painter, &tracer.significant_cycles[*i])
+                )?;
+            }
+        }
+        SignificantCycleDisplay::Collapse => {
+            if !node.significant_cycles.is_empty() {
+                writeln!(
+                    out,
+                    "{}- {} significant cycles take {} cycles",
+                    inner,
+                    node.significant_cycles.len(),
+                    significant_cycles_total(tracer, node),
+                )?;
+            }
+        }
+        SignificantCycleDisplay::Hide => {}
+    }
+    Ok(())
+}
+
+impl Renderer for MarkdownRenderer {
+    fn render_timers(
+        &self,
+        tracer: &CycleTracer,
+        options: &ReportOptions,
+        out: &mut dyn Write,
+    ) -> std::io::Result<()> {
+        for warning in tracer.warnings.iter() {
+            writeln!(out, "> **Warning**: {}\n", warning)?;
+        }
+
+        let roots = tracer.timer_tree();
+        let total_cycles = total_cycles(&roots);
+
+        for node in crate::report::filter_nodes(roots, options, total_cycles, false).iter() {
+            markdown_node(tracer, node, None, total_cycles, options, out)?;
+        }
+
+        for category in tracer.categories().iter() {
+            let roots = tracer.category_timer_tree(category);
+            let total_cycles = crate::report::total_cycles(&roots);
+            writeln!(
+                out,
+                "\n**Category {}**: {} cycles, {} instructions\n",
+                category,
+                total_cycles,
+                total_instructions(&roots),
+            )?;
+            for node in crate::report::filter_nodes(roots, options, total_cycles, false).iter() {
+                markdown_node(tracer, node, None, total_cycles, options, out)?;
+            }
+        }
+
+        if tracer.instrumentation_sequences != 0 {
+            writeln!(
+                out,
+                "\nProfiler overhead: {} timer macros take {} cycles, {} instructions",
+                tracer.instrumentation_sequences,
+                tracer.instrumentation_cycles,
+                tracer.instrumentation_instructions,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn render_segments(&self, tracer: &CycleTracer, out: &mut dyn Write) -> std::io::Result<()> {
+        writeln!(
+            out,
+            "| Segment | Cycles | Instructions | Pages loaded | Pages dirtied | Boundary cycles | Timers |"
+        )?;
+        writeln!(out, "|---:|---|---:|---:|---:|---:|---|")?;
+        for segment in tracer.segments.iter() {
+            writeln!(
+                out,
+                "| {} | {} => {} | {} | {} | {} | {} | {} |",
+                segment.index,
+                segment.start_cycle,
+                segment.end_cycle,
+                segment.num_instructions,
+                segment.num_pages_loaded,
+                segment.num_pages_dirtied,
+                segment.boundary_cycles,
+                escape_cell(&segment.active_timers.join(", ")),
+            )?;
+        }
+        Ok(())
+    }
+
+    fn render_pages(&self, tracer: &CycleTracer, out: &mut dyn Write) -> std::io::Result<()> {
+        writeln!(
+            out,
+            "| Page | Segments loaded | Segments dirtied | Timers |"
+        )?;
+        writeln!(out, "|---|---:|---:|---|")?;
+        for (page_idx, record) in sorted_page_records(tracer).iter() {
+            writeln!(
+                out,
+                "| `{:#08x}` | {} | {} | {} |",
+                page_idx << 10,
+                record.num_segments_loaded,
+                record.num_segments_dirtied,
+                escape_cell(&top_page_timers(record, 3)),
+            )?;
+        }
+
+        writeln!(out, "\n```")?;
+        for (start, end, line) in page_map_lines(tracer).iter() {
+            writeln!(out, "{:#08x} - {:#08x}: {}", start, end, line)?;
+        }
+        writeln!(out, "```")
+    }
+
+    fn render_spans(&self, tracer: &CycleTracer, out: &mut dyn Write) -> std::io::Result<()> {
+        writeln!(
+            out,
+            "| ID | Span | Start cycle | End cycle | Cycles | Instructions |"
+        )?;
+        writeln!(out, "|---:|---|---:|---:|---:|---:|")?;
+        for span in sorted_spans(tracer).iter() {
+            writeln!(
+                out,
+                "| {} | {} | {} | {} | {} | {} |",
+                span.id,
+                escape_cell(&span.name),
+                span.start_cycle,
+                span.start_cycle + span.num_cycles,
+                span.num_cycles,
+                span.num_instructions,
+            )?;
+        }
+        for span in tracer.pending_spans.iter() {
+            writeln!(
+                out,
+                "| {} | {} | {} | not stopped | | |",
+                span.id,
+                escape_cell(&span.name),
+                span.cur_num_cycles,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn render_hotspots(
+        &self,
+        tracer: &CycleTracer,
+        n: usize,
+        out: &mut dyn Write,
+    ) -> std::io::Result<()> {
+        writeln!(
+            out,
+            "| # | Instruction | PC | Extra cycles | Significant cycles | Timers |"
+        )?;
+        writeln!(out, "|---:|---|---|---:|---:|---|")?;
+        for (i, hotspot) in tracer.hotspots().iter().take(n).enumerate() {
+            let timers = hotspot
+                .timers
+                .iter()
+                .map(|timer| {
+                    if timer.is_empty() {
+                        "(no timer)".to_string()
+                    } else {
+                        timer.clone()
+                    }
+                })
+                .collect::<Vec<String>>();
+            writeln!(
+                out,
+                "| {} | `{}` | `{:#08x}` | {} | {} | {} |",
+                i + 1,
+                decode_instruction(hotspot.insn),
+                hotspot.pc,
+                hotspot.extra_cycles,
+                hotspot.count,
+                escape_cell(&timers.join(", ")),
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,67 @@
+// one bit for every 1KB page of the 32-bit guest address space
+const NUM_WORDS: usize = (1 << 22) / 64;
+
+// the words are allocated on the first insert
+#[derive(Default)]
+pub struct PageBitmap {
+    pub words: Vec<u64>,
+    // the words that have any bit set, so that clearing does not go over the whole bitmap
+    pub touched_words: Vec<u32>,
+}
+
+impl PageBitmap {
+    #[inline(always)]
+    pub fn contains(&self, page_idx: u32) -> bool {
+        match self.words.get((page_idx >> 6) as usize) {
+            Some(word) => word & (1 << (page_idx & 63)) != 0,
+            None => false,
+        }
+    }
+
+    // returns whether the page was not there before
+    #[inline(always)]
+    pub fn insert(&mut self, page_idx: u32) -> bool {
+        if self.words.is_empty() {
+            self.words = vec![0u64; NUM_WORDS];
+        }
+        let word = &mut self.words[(page_idx >> 6) as usize];
+        let bit = 1 << (page_idx & 63);
+        if *word & bit != 0 {
+            return false;
+        }
+        if *word == 0 {
+            self.touched_words.push(page_idx >> 6);
+        }
+        *word |= bit;
+        true
+    }
+
+    pub fn clear(&mut self) {
+        for i in self.touched_words.iter() {
+            self.words[*i as usize] = 0;
+        }
+        self.touched_words.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.touched_words
+            .iter()
+            .map(|i| self.words[*i as usize].count_ones() as usize)
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.touched_words.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = u32> + '_ {
+        let mut touched_words = self.touched_words.clone();
+        touched_words.sort();
+        touched_words.into_iter().flat_map(move |i| {
+            let word = self.words[i as usize];
+            (0..64)
+                .filter(move |bit| word & (1 << bit) != 0)
+                .map(move |bit| (i << 6) + bit)
+        })
+    }
+}
@@ -0,0 +1,647 @@
+use crate::report::{total_cycles, total_instructions, TimerNode};
+use crate::{
+    CycleCause, CycleTracer, FinishedSpan, InstructionClass, PageRecord, ReportOptions,
+    SignificantCycleDisplay, SignificantCycleRecord,
+};
+use colored::Colorize;
+use std::fmt::Display;
+use std::io::Write;
+
+pub trait Renderer {
+    fn render_timers(
+        &self,
+        tracer: &CycleTracer,
+        options: &ReportOptions,
+        out: &mut dyn Write,
+    ) -> std::io::Result<()>;
+
+    fn render_segments(&self, tracer: &CycleTracer, out: &mut dyn Write) -> std::io::Result<()>;
+
+    fn render_pages(&self, tracer: &CycleTracer, out: &mut dyn Write) -> std::io::Result<()>;
+
+    fn render_spans(&self, tracer: &CycleTracer, out: &mut dyn Write) -> std::io::Result<()>;
+
+    fn render_hotspots(
+        &self,
+        tracer: &CycleTracer,
+        n: usize,
+        out: &mut dyn Write,
+    ) -> std::io::Result<()>;
+}
+
+// colorized text for the terminal
+pub struct TerminalRenderer;
+
+// the same text as `TerminalRenderer`, without ANSI escape codes
+pub struct PlainTextRenderer;
+
+pub(crate) struct Painter {
+    pub color: bool,
+}
+
+impl Painter {
+    pub fn blue(&self, s: impl Display) -> String {
+        if self.color {
+            format!("{}", s.to_string().as_str().blue())
+        } else {
+            s.to_string()
+        }
+    }
+
+    pub fn white(&self, s: impl Display) -> String {
+        if self.color {
+            format!("{}", s.to_string().as_str().white())
+        } else {
+            s.to_string()
+        }
+    }
+
+    pub fn green(&self, s: impl Display) -> String {
+        if self.color {
+            format!("{}", s.to_string().as_str().green())
+        } else {
+            s.to_string()
+        }
+    }
+
+    pub fn red(&self, s: impl Display) -> String {
+        if self.color {
+            format!("{}", s.to_string().as_str().red())
+        } else {
+            s.to_string()
+        }
+    }
+}
+
+pub(crate) fn percentage(part: u64, whole: u64) -> f64 {
+    if whole == 0 {
+        0.0
+    } else {
+        part as f64 * 100.0 / whole as f64
+    }
+}
+
+pub(crate) fn format_shares(
+    num_cycles: u32,
+    parent_cycles: Option<u32>,
+    total_cycles: u64,
+) -> String {
+    match parent_cycles {
+        Some(parent_cycles) => format!(
+            "{:.2}% of parent, {:.2}% of total",
+            percentage(num_cycles as u64, parent_cycles as u64),
+            percentage(num_cycles as u64, total_cycles),
+        ),
+        None => format!(
+            "{:.2}% of total",
+            percentage(num_cycles as u64, total_cycles)
+        ),
+    }
+}
+
+pub(crate) fn format_cycles_per_instruction(num_cycles: u32, num_instructions: u32) -> String {
+    if num_instructions == 0 {
+        "-".to_string()
+    } else {
+        format!("{:.2}", num_cycles as f64 / num_instructions as f64)
+    }
+}
+
+pub(crate) fn decode_instruction(insn: u32) -> String {
+    use raki::decode::Decode;
+    use raki::Isa;
+
+    match insn.decode(Isa::Rv32) {
+        Ok(instruction) => format!("{}", instruction),
+        Err(_) => format!("{:#010x}", insn),
+    }
+}
+
+pub(crate) fn significant_cycles_total(tracer: &CycleTracer, node: &TimerNode) -> u64 {
+    node.significant_cycles
+        .iter()
+        .map(|i| {
+            let significant_cycle = &tracer.significant_cycles[*i];
+            (significant_cycle.current_cycle - significant_cycle.previous_cycle) as u64
+        })
+        .sum::<u64>()
+}
+
+fn compute_indent(indent_amount: usize) -> String {
+    let mut indent = "····".repeat(indent_amount);
+    if indent_amount != 0 {
+        indent.push(' ');
+    }
+    indent
+}
+
+fn format_list(painter: &Painter, list: &[u32], shift: u32) -> String {
+    let mut sorted = list.to_vec();
+    sorted.sort();
+
+    let str = sorted
+        .iter()
+        .take(4)
+        .map(|x| format!("{:#08x}", x << shift))
+        .collect::<Vec<String>>();
+    if sorted.len() > 4 {
+        let last = format!("{:#08x}", sorted[sorted.len() - 1] << shift);
+        format!(
+            "{}, ..., {}",
+            painter.white(str.join(", ")),
+            painter.white(last)
+        )
+    } else {
+        painter.white(str.join(", "))
+    }
+}
+
+pub(crate) fn format_cycle_cause(painter: &Painter, cause: &CycleCause) -> String {
+    match cause {
+        CycleCause::SegmentBoundary => "starts the new segment".to_string(),
+        CycleCause::CodePageIn(pages) => {
+            format!("loads code pages {}", format_list(painter, pages, 10))
+        }
+        CycleCause::DataPageIn(pages) => {
+            format!("loads data pages {}", format_list(painter, pages, 10))
+        }
+        CycleCause::PageDirtied(pages) => {
+            format!("marks pages {} as dirty", format_list(painter, pages, 10))
+        }
+        CycleCause::Syscall(name) => format!("makes the syscall {}", painter.white(name)),
+        CycleCause::JumpIntoNewPage { pc, insn, pages } => format!(
+            "jumps from {} at {} into pages {}",
+            painter.blue(decode_instruction(*insn)),
+            painter.white(format!("{:#08x}", pc)),
+            format_list(painter, pages, 10)
+        ),
+        CycleCause::Branch { pc, insn, pages } => format!(
+            "branches from {} at {} into pages {}",
+            painter.blue(decode_instruction(*insn)),
+            painter.white(format!("{:#08x}", pc)),
+            format_list(painter, pages, 10)
+        ),
+    }
+}
+
+pub(crate) fn format_significant_cycle(
+    painter: &Painter,
+    significant_cycle: &SignificantCycleRecord,
+) -> String {
+    let causes = significant_cycle
+        .causes
+        .iter()
+        .map(|(cause, cycles)| {
+            format!(
+                "{} (~{} cycles)",
+                format_cycle_cause(painter, cause),
+                painter.blue(cycles)
+            )
+        })
+        .collect::<Vec<String>>();
+    let explanation = if causes.is_empty() {
+        "".to_string()
+    } else {
+        format!(": it {}", causes.join(", "))
+    };
+
+    let writes = if significant_cycle.latest_io_addrs.is_empty() {
+        "".to_string()
+    } else {
+        format!(
+            " that writes to {}",
+            format_list(painter, &significant_cycle.latest_io_addrs, 0)
+        )
+    };
+
+    format!(
+        "Cycle: {} => {}: {} at {}{} takes {} cycles{}",
+        significant_cycle.previous_cycle,
+        significant_cycle.current_cycle,
+        painter.blue(decode_instruction(significant_cycle.insn)),
+        painter.white(format!("{:#08x}", significant_cycle.pc)),
+        writes,
+        painter.blue(significant_cycle.current_cycle - significant_cycle.previous_cycle),
+        explanation,
+    )
+}
+
+pub(crate) fn sorted_spans(tracer: &CycleTracer) -> Vec<&FinishedSpan> {
+    let mut sorted = tracer.finished_spans.iter().collect::<Vec<&FinishedSpan>>();
+    sorted.sort_by_key(|span| (span.start_cycle, span.id));
+    sorted
+}
+
+pub(crate) fn sorted_page_records(tracer: &CycleTracer) -> Vec<(u32, &PageRecord)> {
+    let mut sorted = tracer
+        .page_records
+        .iter()
+        .map(|(page_idx, record)| (*page_idx, record))
+        .collect::<Vec<(u32, &PageRecord)>>();
+    sorted.sort_by_key(|(page_idx, record)| {
+        (
+            std::cmp::Reverse(record.num_segments_loaded + record.num_segments_dirtied),
+            *page_idx,
+        )
+    });
+    sorted
+}
+
+pub(crate) fn top_page_timers(record: &PageRecord, n: usize) -> String {
+    let mut timers = record.timers.iter().collect::<Vec<(&String, &u32)>>();
+    timers.sort_by_key(|(_, count)| std::cmp::Reverse(**count));
+
+    let timer_string = timers
+        .iter()
+        .take(n)
+        .map(|(name, count)| format!("{} ({})", name, count))
+        .collect::<Vec<String>>();
+    let more_string = if timers.len() > n { ", ..." } else { "" };
+    format!("{}{}", timer_string.join(", "), more_string)
+}
+
+// consecutive pages that have been accessed, as (first page, last page)
+pub(crate) fn page_ranges(tracer: &CycleTracer) -> Vec<(u32, u32)> {
+    let mut ranges: Vec<(u32, u32)> = Vec::new();
+    for page_idx in tracer.page_records.keys() {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == *page_idx => *end = *page_idx,
+            _ => ranges.push((*page_idx, *page_idx)),
+        }
+    }
+    ranges
+}
+
+// every page is one character, darker if it is brought in more often
+pub(crate) fn page_map_lines(tracer: &CycleTracer) -> Vec<(u32, u32, String)> {
+    const SHADES: &[char] = &['.', ':', '-', '=', '+', '*', '#', '%', '@'];
+    const PAGES_PER_LINE: u32 = 64;
+
+    let max_count = tracer
+        .page_records
+        .values()
+        .map(|record| record.num_segments_loaded)
+        .max()
+        .unwrap_or(0)
+        .max(1);
+
+    let mut lines = Vec::new();
+    for (start, end) in page_ranges(tracer).iter() {
+        let mut line_start = *start;
+        while line_start <= *end {
+            let line_end = std::cmp::min(line_start + PAGES_PER_LINE - 1, *end);
+            let mut line = String::new();
+            for page_idx in line_start..=line_end {
+                let count = tracer.page_records[&page_idx].num_segments_loaded as usize;
+                let shade = (count * SHADES.len()).saturating_sub(1) / max_count as usize;
+                line.push(SHADES[shade]);
+            }
+            lines.push((line_start << 10, ((line_end + 1) << 10) - 1, line));
+            line_start = line_end + 1;
+        }
+    }
+    lines
+}
+
+fn text_timers(
+    painter: &Painter,
+    tracer: &CycleTracer,
+    options: &ReportOptions,
+    out: &mut dyn Write,
+) -> std::io::Result<()> {
+    for warning in tracer.warnings.iter() {
+        writeln!(out, "{}", painter.red(format!("Warning: {}", warning)))?;
+    }
+
+    let roots = tracer.timer_tree();
+    let total_cycles = total_cycles(&roots);
+
+    let mut output = String::new();
+    for node in crate::report::filter_nodes(roots, options, total_cycles, false).iter() {
+        text_node(
+            painter,
+            tracer,
+            node,
+            None,
+            total_cycles,
+            options,
+            &mut output,
+        );
+    }
+
+    // the shares of the timers in a category are of the total of that category
+    for category in tracer.categories().iter() {
+        let roots = tracer.category_timer_tree(category);
+        let total_cycles = crate::report::total_cycles(&roots);
+        output += &format!(
+            "Category {}: {} cycles, {} instructions\n",
+            painter.white(category),
+            painter.blue(total_cycles),
+            painter.blue(total_instructions(&roots)),
+        );
+        for node in crate::report::filter_nodes(roots, options, total_cycles, false).iter() {
+            text_node(
+                painter,
+                tracer,
+                node,
+                None,
+                total_cycles,
+                options,
+                &mut output,
+            );
+        }
+    }
+    writeln!(out, "{}", painter.green(output))?;
+
+    if tracer.instrumentation_sequences != 0 {
+        writeln!(
+            out,
+            "Profiler overhead: {} timer macros take {} cycles, {} instructions",
+            painter.blue(tracer.instrumentation_sequences),
+            painter.blue(tracer.instrumentation_cycles),
+            painter.blue(tracer.instrumentation_instructions),
+        )?;
+    }
+    Ok(())
+}
+
+fn text_node(
+    painter: &Painter,
+    tracer: &CycleTracer,
+    node: &TimerNode,
+    parent_cycles: Option<u32>,
+    total_cycles: u64,
+    options: &ReportOptions,
+    output: &mut String,
+) {
+    let report = node.record;
+    let level = node.depth;
+
+    let overhead = if tracer.instrumentation_sequences == 0 {
+        "".to_string()
+    } else {
+        format!(
+            " ({} cycles, {} instructions without profiler overhead)",
+            painter.blue(report.num_cycles - report.instrumentation_cycles),
+            painter.blue(report.num_instructions - report.instrumentation_instructions),
+        )
+    };
+
+    *output += &format!(
+        "{}{}: {} cycles ({}), {} instructions, {} self cycles, {} cycles per instruction{}\n",
+        compute_indent(level),
+        report.name,
+        painter.blue(report.num_cycles),
+        format_shares(report.num_cycles, parent_cycles, total_cycles),
+        painter.blue(report.num_instructions),
+        painter.blue(node.self_cycles),
+        format_cycles_per_instruction(report.num_cycles, report.num_instructions),
+        overhead,
+    );
+
+    let mix = InstructionClass::ALL
+        .iter()
+        .filter(|class| report.instruction_mix.get(**class).0 != 0)
+        .map(|class| {
+            let (num_instructions, num_cycles) = report.instruction_mix.get(*class);
+            format!(
+                "{} {} ({} cycles)",
+                class.name(),
+                painter.blue(num_instructions),
+                num_cycles,
+            )
+        })
+        .collect::<Vec<String>>();
+    if !mix.is_empty() {
+        *output += &format!(
+            "{}Instructions: {}\n",
+            compute_indent(level + 1),
+            mix.join(", ")
+        );
+    }
+
+    for (name, syscall) in report.syscalls.iter() {
+        *output += &format!(
+            "{}{}: {} calls, {} cycles\n",
+            compute_indent(level + 1),
+            painter.white(name),
+            painter.blue(syscall.num_calls),
+            painter.blue(syscall.num_cycles),
+        );
+    }
+
+    for child in node.children.iter() {
+        text_node(
+            painter,
+            tracer,
+            child,
+            Some(report.num_cycles),
+            total_cycles,
+            options,
+            output,
+        );
+    }
+
+    // the part of a timer with children that is not covered by any of them
+    if report.num_cycles != node.self_cycles && node.self_cycles != 0 {
+        *output += &format!(
+            "{}(untracked): {} cycles ({}), {} instructions\n",
+            compute_indent(level + 1),
+            painter.blue(node.self_cycles),
+            format_shares(node.self_cycles, Some(report.num_cycles), total_cycles),
+            painter.blue(node.self_instructions),
+        );
+    }
+
+    match options.significant_cycles {
+        SignificantCycleDisplay::Show => {
+            for i in node.significant_cycles.iter() {
+                *output += &format!(
+                    "{}{}\n",
+                    compute_indent(level + 1),
+                    format_significant_cycle(painter, &tracer.significant_cycles[*i])
+                );
+            }
+        }
+        SignificantCycleDisplay::Collapse => {
+            if !node.significant_cycles.is_empty() {
+                *output += &format!(
+                    "{}{} significant cycles take {} cycles\n",
+                    compute_indent(level + 1),
+                    painter.blue(node.significant_cycles.len()),
+                    painter.blue(significant_cycles_total(tracer, node)),
+                );
+            }
+        }
+        SignificantCycleDisplay::Hide => {}
+    }
+}
+
+fn text_segments(
+    painter: &Painter,
+    tracer: &CycleTracer,
+    out: &mut dyn Write,
+) -> std::io::Result<()> {
+    let mut output = String::new();
+    for segment in tracer.segments.iter() {
+        output += &format!(
+            "Segment {}: cycles {} => {}, {} instructions, {} pages loaded, {} pages dirtied, {} cycles at the boundary\n",
+            segment.index,
+            segment.start_cycle,
+            segment.end_cycle,
+            painter.blue(segment.num_instructions),
+            painter.blue(segment.num_pages_loaded),
+            painter.blue(segment.num_pages_dirtied),
+            painter.blue(segment.boundary_cycles),
+        );
+        if !segment.active_timers.is_empty() {
+            output += &format!(
+                "···· timers: {}\n",
+                painter.white(segment.active_timers.join(", "))
+            );
+        }
+    }
+
+    writeln!(out, "{}", painter.green(output))
+}
+
+fn text_pages(painter: &Painter, tracer: &CycleTracer, out: &mut dyn Write) -> std::io::Result<()> {
+    let mut output = String::new();
+    for (page_idx, record) in sorted_page_records(tracer).iter() {
+        output += &format!(
+            "Page {}: loaded in {} segments, dirtied in {} segments, by {}\n",
+            painter.white(format!("{:#08x}", page_idx << 10)),
+            painter.blue(record.num_segments_loaded),
+            painter.blue(record.num_segments_dirtied),
+            top_page_timers(record, 3),
+        );
+    }
+    writeln!(out, "{}", painter.green(output))?;
+
+    let mut output = String::new();
+    for (start, end, line) in page_map_lines(tracer).iter() {
+        output += &format!(
+            "{} - {}: {}\n",
+            painter.white(format!("{:#08x}", start)),
+            painter.white(format!("{:#08x}", end)),
+            line,
+        );
+    }
+    writeln!(out, "{}", painter.green(output))
+}
+
+fn text_spans(painter: &Painter, tracer: &CycleTracer, out: &mut dyn Write) -> std::io::Result<()> {
+    let mut output = String::new();
+    for span in sorted_spans(tracer).iter() {
+        output += &format!(
+            "Span {} {}: cycles {} => {}, {} cycles, {} instructions\n",
+            span.id,
+            span.name,
+            span.start_cycle,
+            span.start_cycle + span.num_cycles,
+            painter.blue(span.num_cycles),
+            painter.blue(span.num_instructions),
+        );
+    }
+    for span in tracer.pending_spans.iter() {
+        output += &format!(
+            "Span {} {}: started at cycle {}, not stopped\n",
+            span.id, span.name, span.cur_num_cycles,
+        );
+    }
+
+    writeln!(out, "{}", painter.green(output))
+}
+
+fn text_hotspots(
+    painter: &Painter,
+    tracer: &CycleTracer,
+    n: usize,
+    out: &mut dyn Write,
+) -> std::io::Result<()> {
+    let mut output = String::new();
+    for (i, hotspot) in tracer.hotspots().iter().take(n).enumerate() {
+        output += &format!(
+            "#{}: {} at {} takes {} extra cycles in {} significant cycles\n",
+            i + 1,
+            painter.blue(decode_instruction(hotspot.insn)),
+            painter.white(format!("{:#08x}", hotspot.pc)),
+            painter.blue(hotspot.extra_cycles),
+            painter.blue(hotspot.count),
+        );
+        for timer in hotspot.timers.iter() {
+            let timer = if timer.is_empty() {
+                "(no timer)"
+            } else {
+                timer.as_str()
+            };
+            output += &format!("···· in {}\n", painter.white(timer));
+        }
+    }
+
+    writeln!(out, "{}", painter.green(output))
+}
+
+impl Renderer for TerminalRenderer {
+    fn render_timers(
+        &self,
+        tracer: &CycleTracer,
+        options: &ReportOptions,
+        out: &mut dyn Write,
+    ) -> std::io::Result<()> {
+        text_timers(&Painter { color: true }, tracer, options, out)
+    }
+
+    fn render_segments(&self, tracer: &CycleTracer, out: &mut dyn Write) -> std::io::Result<()> {
+        text_segments(&Painter { color: true }, tracer, out)
+    }
+
+    fn render_pages(&self, tracer: &CycleTracer, out: &mut dyn Write) -> std::io::Result<()> {
+        text_pages(&Painter { color: true }, tracer, out)
+    }
+
+    fn render_spans(&self, tracer: &CycleTracer, out: &mut dyn Write) -> std::io::Result<()> {
+        text_spans(&Painter { color: true }, tracer, out)
+    }
+
+    fn render_hotspots(
+        &self,
+        tracer: &CycleTracer,
+        n: usize,
+        out: &mut dyn Write,
+    ) -> std::io::Result<()> {
+        text_hotspots(&Painter { color: true }, tracer, n, out)
+    }
+}
+
+impl Renderer for PlainTextRenderer {
+    fn render_timers(
+        &self,
+        tracer: &CycleTracer,
+        options: &ReportOptions,
+        out: &mut dyn Write,
+    ) -> std::io::Result<()> {
+        text_timers(&Painter { color: false }, tracer, options, out)
+    }
+
+    fn render_segments(&self, tracer: &CycleTracer, out: &mut dyn Write) -> std::io::Result<()> {
+        text_segments(&Painter { color: false }, tracer, out)
+    }
+
+    fn render_pages(&self, tracer: &CycleTracer, out: &mut dyn Write) -> std::io::Result<()> {
+        text_pages(&Painter { color: false }, tracer, out)
+    }
+
+    fn render_spans(&self, tracer: &CycleTracer, out: &mut dyn Write) -> std::io::Result<()> {
+        text_spans(&Painter { color: false }, tracer, out)
+    }
+
+    fn render_hotspots(
+        &self,
+        tracer: &CycleTracer,
+        n: usize,
+        out: &mut dyn Write,
+    ) -> std::io::Result<()> {
+        text_hotspots(&Painter { color: false }, tracer, n, out)
+    }
+}
@@ -0,0 +1,210 @@
+use crate::CycleTracer;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use risc0_zkvm::TraceEvent;
+use std::io::{BufReader, Read, Write};
+
+// written before the compressed events, the last byte is the version of the format
+const MAGIC: &[u8; 8] = b"L2R0TRC\x01";
+
+const TAG_INSTRUCTION_START: u8 = 0;
+const TAG_REGISTER_SET: u8 = 1;
+const TAG_MEMORY_SET: u8 = 2;
+
+fn write_varint<W: Write>(writer: &mut W, mut value: u32) -> std::io::Result<()> {
+    let mut buf = [0u8; 5];
+    let mut len = 0;
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            buf[len] = byte;
+            len += 1;
+            break;
+        }
+        buf[len] = byte | 0x80;
+        len += 1;
+    }
+    writer.write_all(&buf[..len])
+}
+
+// returns `None` at the end of the stream
+fn read_byte<R: Read>(reader: &mut R) -> std::io::Result<Option<u8>> {
+    let mut buf = [0u8; 1];
+    loop {
+        match reader.read(&mut buf) {
+            Ok(0) => return Ok(None),
+            Ok(_) => return Ok(Some(buf[0])),
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+fn read_varint<R: Read>(reader: &mut R) -> std::io::Result<u32> {
+    let mut value = 0u32;
+    for i in 0..5 {
+        let byte = match read_byte(reader)? {
+            Some(byte) => byte,
+            None => return Err(std::io::ErrorKind::UnexpectedEof.into()),
+        };
+        value |= ((byte & 0x7f) as u32) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        "invalid varint in the recorded trace",
+    ))
+}
+
+// small negative deltas, such as a backward jump, also take few bytes
+fn zigzag(value: i32) -> u32 {
+    ((value << 1) ^ (value >> 31)) as u32
+}
+
+fn unzigzag(value: u32) -> i32 {
+    ((value >> 1) as i32) ^ -((value & 1) as i32)
+}
+
+// the cycles and the program counters are stored as the difference from those of the previous instruction
+pub struct TraceRecorder<W: Write> {
+    pub encoder: DeflateEncoder<W>,
+    pub previous_cycle: u32,
+    pub previous_pc: u32,
+}
+
+impl<W: Write> TraceRecorder<W> {
+    pub fn new(mut writer: W) -> std::io::Result<Self> {
+        writer.write_all(MAGIC)?;
+        Ok(Self {
+            encoder: DeflateEncoder::new(writer, Compression::default()),
+            previous_cycle: 0,
+            previous_pc: 0,
+        })
+    }
+
+    pub fn record(&mut self, event: &TraceEvent) -> std::io::Result<()> {
+        match event {
+            TraceEvent::InstructionStart { cycle, pc, insn } => {
+                self.encoder.write_all(&[TAG_INSTRUCTION_START])?;
+                write_varint(&mut self.encoder, cycle.wrapping_sub(self.previous_cycle))?;
+                // the next instruction is usually 4 bytes after
+                write_varint(
+                    &mut self.encoder,
+                    zigzag(pc.wrapping_sub(self.previous_pc).wrapping_sub(4) as i32),
+                )?;
+                self.encoder.write_all(&insn.to_le_bytes())?;
+                self.previous_cycle = *cycle;
+                self.previous_pc = *pc;
+            }
+            TraceEvent::RegisterSet { idx, value } => {
+                self.encoder.write_all(&[TAG_REGISTER_SET, *idx as u8])?;
+                write_varint(&mut self.encoder, *value)?;
+            }
+            TraceEvent::MemorySet { addr, region } => {
+                self.encoder.write_all(&[TAG_MEMORY_SET])?;
+                write_varint(&mut self.encoder, *addr)?;
+                write_varint(&mut self.encoder, region.len() as u32)?;
+                self.encoder.write_all(region)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn finish(self) -> std::io::Result<W> {
+        self.encoder.finish()
+    }
+}
+
+pub struct TraceReplayer<R: Read> {
+    pub decoder: BufReader<DeflateDecoder<R>>,
+    pub previous_cycle: u32,
+    pub previous_pc: u32,
+}
+
+impl<R: Read> TraceReplayer<R> {
+    pub fn new(mut reader: R) -> std::io::Result<Self> {
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "not a recorded trace, or recorded by an incompatible version",
+            ));
+        }
+        Ok(Self {
+            decoder: BufReader::new(DeflateDecoder::new(reader)),
+            previous_cycle: 0,
+            previous_pc: 0,
+        })
+    }
+
+    pub fn next_event(&mut self) -> std::io::Result<Option<TraceEvent>> {
+        let tag = match read_byte(&mut self.decoder)? {
+            Some(tag) => tag,
+            None => return Ok(None),
+        };
+        let event = match tag {
+            TAG_INSTRUCTION_START => {
+                let cycle = self
+                    .previous_cycle
+                    .wrapping_add(read_varint(&mut self.decoder)?);
+                let pc = self
+                    .previous_pc
+                    .wrapping_add(4)
+                    .wrapping_add(unzigzag(read_varint(&mut self.decoder)?) as u32);
+                let mut insn = [0u8; 4];
+                self.decoder.read_exact(&mut insn)?;
+                self.previous_cycle = cycle;
+                self.previous_pc = pc;
+                TraceEvent::InstructionStart {
+                    cycle,
+                    pc,
+                    insn: u32::from_le_bytes(insn),
+                }
+            }
+            TAG_REGISTER_SET => {
+                let mut idx = [0u8; 1];
+                self.decoder.read_exact(&mut idx)?;
+                TraceEvent::RegisterSet {
+                    idx: idx[0] as usize,
+                    value: read_varint(&mut self.decoder)?,
+                }
+            }
+            TAG_MEMORY_SET => {
+                let addr = read_varint(&mut self.decoder)?;
+                let len = read_varint(&mut self.decoder)? as usize;
+                let mut region = vec![0u8; len];
+                self.decoder.read_exact(&mut region)?;
+                TraceEvent::MemorySet { addr, region }
+            }
+            _ => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("unknown event {} in the recorded trace", tag),
+                ))
+            }
+        };
+        Ok(Some(event))
+    }
+}
+
+impl<R: Read> Iterator for TraceReplayer<R> {
+    type Item = std::io::Result<TraceEvent>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_event().transpose()
+    }
+}
+
+impl CycleTracer {
+    pub fn replay<R: Read>(&mut self, reader: R) -> std::io::Result<()> {
+        for event in TraceReplayer::new(reader)? {
+            self.handle_event(event?);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,243 @@
+use crate::render::{Renderer, TerminalRenderer};
+use crate::{CycleTracer, FinishedRecord};
+
+pub struct TimerNode<'a> {
+    pub record: &'a FinishedRecord,
+    pub path: String,
+    pub depth: usize,
+    // the cycles and instructions that are not inside any of the children
+    pub self_cycles: u32,
+    pub self_instructions: u32,
+    // the significant cycles that are not inside any of the children
+    pub significant_cycles: Vec<usize>,
+    pub children: Vec<TimerNode<'a>>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SignificantCycleDisplay {
+    Show,
+    Collapse,
+    Hide,
+}
+
+pub enum NamePattern {
+    Glob(String),
+    Regex(regex::Regex),
+}
+
+impl NamePattern {
+    pub fn glob(pattern: &str) -> Self {
+        NamePattern::Glob(pattern.to_string())
+    }
+
+    pub fn regex(pattern: &str) -> Result<Self, regex::Error> {
+        Ok(NamePattern::Regex(regex::Regex::new(pattern)?))
+    }
+
+    // a pattern matches a timer if it matches either its name or its path, such as `Total/Load data/Hash`
+    pub fn matches(&self, node: &TimerNode) -> bool {
+        match self {
+            NamePattern::Glob(pattern) => {
+                glob_match(pattern.as_bytes(), node.record.name.as_bytes())
+                    || glob_match(pattern.as_bytes(), node.path.as_bytes())
+            }
+            NamePattern::Regex(regex) => {
+                regex.is_match(&node.record.name) || regex.is_match(&node.path)
+            }
+        }
+    }
+}
+
+// on a mismatch, only the last `*` needs to take one more byte, so this is linear in the text for every `*`
+fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    // the position after the last `*`, and the text that it has taken up to
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && pattern[p] == b'*' {
+            star = Some((p + 1, t));
+            p += 1;
+        } else if p < pattern.len() && (pattern[p] == b'?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if let Some((star_p, star_t)) = star {
+            star = Some((star_p, star_t + 1));
+            p = star_p;
+            t = star_t + 1;
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == b'*')
+}
+
+pub struct ReportOptions {
+    pub max_depth: Option<usize>,
+    // timers that take less than this share of the total cycles are hidden
+    pub min_share: f64,
+    pub significant_cycles: SignificantCycleDisplay,
+    pub include: Vec<NamePattern>,
+    pub exclude: Vec<NamePattern>,
+}
+
+impl Default for ReportOptions {
+    fn default() -> Self {
+        Self {
+            max_depth: None,
+            min_share: 0.0,
+            significant_cycles: SignificantCycleDisplay::Show,
+            include: Vec::new(),
+            exclude: Vec::new(),
+        }
+    }
+}
+
+fn assign_paths(nodes: &mut [TimerNode], prefix: &str) {
+    for node in nodes.iter_mut() {
+        node.path = if prefix.is_empty() {
+            node.record.name.clone()
+        } else {
+            format!("{}/{}", prefix, node.record.name)
+        };
+        let path = node.path.clone();
+        assign_paths(&mut node.children, &path);
+    }
+}
+
+pub(crate) fn filter_nodes<'a>(
+    nodes: Vec<TimerNode<'a>>,
+    options: &ReportOptions,
+    total_cycles: u64,
+    included: bool,
+) -> Vec<TimerNode<'a>> {
+    nodes
+        .into_iter()
+        .filter_map(|mut node| {
+            if matches!(options.max_depth, Some(max_depth) if node.depth >= max_depth) {
+                return None;
+            }
+            if (node.record.num_cycles as f64) < options.min_share * total_cycles as f64 {
+                return None;
+            }
+            if options.exclude.iter().any(|pattern| pattern.matches(&node)) {
+                return None;
+            }
+
+            // once a timer is included, so are all the timers inside it
+            let included = included
+                || options.include.is_empty()
+                || options.include.iter().any(|pattern| pattern.matches(&node));
+            node.children = filter_nodes(node.children, options, total_cycles, included);
+            if !included && node.children.is_empty() {
+                return None;
+            }
+            Some(node)
+        })
+        .collect()
+}
+
+pub(crate) fn total_cycles(roots: &[TimerNode]) -> u64 {
+    roots
+        .iter()
+        .map(|node| node.record.num_cycles as u64)
+        .sum::<u64>()
+}
+
+pub(crate) fn total_instructions(roots: &[TimerNode]) -> u64 {
+    roots
+        .iter()
+        .map(|node| node.record.num_instructions as u64)
+        .sum::<u64>()
+}
+
+impl CycleTracer {
+    // the categories other than the default one, in the order their first timers finish
+    pub fn categories(&self) -> Vec<String> {
+        let mut categories: Vec<String> = Vec::new();
+        for record in self.finished_records.iter() {
+            if !record.category.is_empty() && !categories.contains(&record.category) {
+                categories.push(record.category.clone());
+            }
+        }
+        categories
+    }
+
+    pub fn timer_tree(&self) -> Vec<TimerNode<'_>> {
+        self.category_timer_tree("")
+    }
+
+    pub fn category_timer_tree(&self, category: &str) -> Vec<TimerNode<'_>> {
+        // the records are in the order they finish, so the children of a timer come right before it
+        let mut levels: Vec<Vec<TimerNode>> = Vec::new();
+        for record in self
+            .finished_records
+            .iter()
+            .filter(|record| record.category == category)
+        {
+            let depth = record.indents;
+            while levels.len() < depth + 2 {
+                levels.push(Vec::new());
+            }
+            let children = std::mem::take(&mut levels[depth + 1]);
+
+            let mut significant_cycles = Vec::new();
+            let mut i = record.start_significant_cycles;
+            for child in children.iter() {
+                while i < child.record.start_significant_cycles {
+                    significant_cycles.push(i);
+                    i += 1;
+                }
+                i = i.max(child.record.end_significant_cycles);
+            }
+            while i < record.end_significant_cycles {
+                significant_cycles.push(i);
+                i += 1;
+            }
+
+            let self_cycles = record.num_cycles
+                - children
+                    .iter()
+                    .map(|child| child.record.num_cycles)
+                    .sum::<u32>();
+            let self_instructions = record.num_instructions
+                - children
+                    .iter()
+                    .map(|child| child.record.num_instructions)
+                    .sum::<u32>();
+
+            levels[depth].push(TimerNode {
+                record,
+                path: String::new(),
+                depth,
+                self_cycles,
+                self_instructions,
+                significant_cycles,
+                children,
+            });
+        }
+
+        let mut roots = levels.into_iter().next().unwrap_or_default();
+        assign_paths(&mut roots, "");
+        roots
+    }
+
+    pub fn filtered_timer_tree(&self, options: &ReportOptions) -> Vec<TimerNode<'_>> {
+        self.filtered_category_timer_tree("", options)
+    }
+
+    pub fn filtered_category_timer_tree(
+        &self,
+        category: &str,
+        options: &ReportOptions,
+    ) -> Vec<TimerNode<'_>> {
+        let roots = self.category_timer_tree(category);
+        let total_cycles = total_cycles(&roots);
+        filter_nodes(roots, options, total_cycles, false)
+    }
+
+    pub fn print_with(&self, options: &ReportOptions) {
+        TerminalRenderer
+            .render_timers(self, options, &mut std::io::stdout())
+            .unwrap();
+    }
+}
@@ -0,0 +1,68 @@
+use crate::{CycleTracer, ProfileEvent};
+
+pub struct Session {
+    pub label: String,
+    pub tracer: CycleTracer,
+}
+
+impl CycleTracer {
+    // starts over with the channels, timers and pages, for the next execution
+    pub fn begin_session(&mut self, label: &str) {
+        if self.session_label.is_some() {
+            self.end_session();
+        }
+        self.start_over();
+        self.session_label = Some(label.to_string());
+        self.emit(ProfileEvent::SessionStart {
+            label: label.to_string(),
+        });
+    }
+
+    // keeps what has been recorded since `begin_session` in `sessions`
+    pub fn end_session(&mut self) {
+        let label = match self.session_label.take() {
+            Some(label) => label,
+            None => return,
+        };
+        // the sink is kept for the next session, and an error of a `WriterSink` is returned again by the last `finish`
+        let _ = self.finish();
+        self.emit(ProfileEvent::SessionEnd);
+        let tracer = self.start_over();
+        self.sessions.push(Session { label, tracer });
+    }
+
+    // the sessions and the configuration stay with the handle, and everything else, including the guest ELF, is returned
+    fn start_over(&mut self) -> CycleTracer {
+        let tracer = CycleTracer {
+            sessions: std::mem::take(&mut self.sessions),
+            sample_interval: self.sample_interval,
+            sink: self.sink.take(),
+            retain_records: self.retain_records,
+            ..Default::default()
+        };
+        let mut previous = std::mem::replace(self, tracer);
+
+        // the bitmaps are large, so they are reused instead of being kept with the previous session
+        std::mem::swap(&mut self.page_accessed, &mut previous.page_accessed);
+        std::mem::swap(&mut self.page_loaded, &mut previous.page_loaded);
+        self.page_accessed.clear();
+        self.page_loaded.clear();
+        previous
+    }
+
+    pub fn session(&self, label: &str) -> Option<&CycleTracer> {
+        self.sessions
+            .iter()
+            .find(|session| session.label == label)
+            .map(|session| &session.tracer)
+    }
+
+    pub fn print_sessions(&self) {
+        use colored::Colorize;
+
+        for session in self.sessions.iter() {
+            println!("{}", format!("Session {}:", session.label).white());
+            session.tracer.print();
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use crate::{CycleCause, CycleTracer, FinishedRecord, SegmentRecord, SignificantCycleRecord};
+use std::io::Write;
+use std::sync::mpsc::Sender;
+
+#[derive(Clone)]
+pub enum ProfileEvent {
+    TimerStart {
+        name: String,
+        category: String,
+        depth: usize,
+        cycle: u32,
+    },
+    TimerStop(FinishedRecord),
+    SignificantCycle(SignificantCycleRecord),
+    SegmentStart {
+        index: u32,
+        cycle: u32,
+    },
+    SegmentEnd(SegmentRecord),
+    // the cycles and the segment indices start again from zero in every session
+    SessionStart {
+        label: String,
+    },
+    SessionEnd,
+}
+
+pub trait EventSink {
+    fn handle(&mut self, event: &ProfileEvent);
+
+    // reports an error of the sink, if any, at the end of the execution
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<F: FnMut(&ProfileEvent)> EventSink for F {
+    fn handle(&mut self, event: &ProfileEvent) {
+        self(event)
+    }
+}
+
+// sends the events to another thread, and stops quietly if the receiver is gone
+pub struct ChannelSink(pub Sender<ProfileEvent>);
+
+impl EventSink for ChannelSink {
+    fn handle(&mut self, event: &ProfileEvent) {
+        let _ = self.0.send(event.clone());
+    }
+}
+
+// one line per event with the fields separated by tabs, and the name, which may contain tabs, at the end
+//
+// it stops writing after the first error, which `flush` returns
+pub struct WriterSink<W: Write> {
+    pub writer: W,
+    pub error: Option<std::io::Error>,
+}
+
+impl<W: Write> WriterSink<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            error: None,
+        }
+    }
+}
+
+impl<W: Write> EventSink for WriterSink<W> {
+    fn handle(&mut self, event: &ProfileEvent) {
+        if self.error.is_some() {
+            return;
+        }
+        let result = match event {
+            ProfileEvent::TimerStart {
+                name,
+                category,
+                depth,
+                cycle,
+            } => writeln!(
+                self.writer,
+                "timer_start\t{}\t{}\t{}\t{}",
+                cycle, depth, category, name
+            ),
+            ProfileEvent::TimerStop(record) => writeln!(
+                self.writer,
+                "timer_stop\t{}\t{}\t{}\t{}\t{}\t{}",
+                record.start_cycle + record.num_cycles,
+                record.indents,
+                record.num_cycles,
+                record.num_instructions,
+                record.category,
+                record.name
+            ),
+            ProfileEvent::SignificantCycle(significant_cycle) => writeln!(
+                self.writer,
+                "significant_cycle\t{}\t{}\t{:#08x}\t{:#010x}\t{}\t{}",
+                significant_cycle.previous_cycle,
+                significant_cycle.current_cycle,
+                significant_cycle.pc,
+                significant_cycle.insn,
+                format_causes(&significant_cycle.causes),
+                significant_cycle.timer_path
+            ),
+            ProfileEvent::SegmentStart { index, cycle } => {
+                writeln!(self.writer, "segment_start\t{}\t{}", cycle, index)
+            }
+            ProfileEvent::SegmentEnd(segment) => writeln!(
+                self.writer,
+                "segment_end\t{}\t{}\t{}\t{}\t{}\t{}",
+                segment.end_cycle,
+                segment.index,
+                segment.num_instructions,
+                segment.num_pages_loaded,
+                segment.num_pages_dirtied,
+                segment.boundary_cycles
+            ),
+            ProfileEvent::SessionStart { label } => {
+                writeln!(self.writer, "session_start\t{}", label)
+            }
+            ProfileEvent::SessionEnd => writeln!(self.writer, "session_end"),
+        };
+        if let Err(error) = result {
+            self.error = Some(error);
+        }
+    }
+
+    // the error stays, so that it is returned again if the sink is flushed after another session
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self.error.as_ref() {
+            Some(error) => Err(std::io::Error::new(error.kind(), error.to_string())),
+            None => self.writer.flush(),
+        }
+    }
+}
+
+// such as `code_page_in:1094,syscall:200`, or `-` if there is no cause
+fn format_causes(causes: &[(CycleCause, u32)]) -> String {
+    if causes.is_empty() {
+        return "-".to_string();
+    }
+    causes
+        .iter()
+        .map(|(cause, cycles)| format!("{}:{}", cause.name(), cycles))
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+impl CycleTracer {
+    pub(crate) fn emit(&mut self, event: ProfileEvent) {
+        if let Some(sink) = self.sink.as_mut() {
+            sink.handle(&event);
+        }
+    }
+
+    // the last segment only ends with the execution, which the trace does not show
+    //
+    // returns the first error of the sink, such as a `WriterSink` whose file cannot be written
+    //
+    // it may be called more than once, such as by `end_session` and then by the host, but the segment only ends once
+    pub fn finish(&mut self) -> std::io::Result<()> {
+        if self.sink.is_none() {
+            return Ok(());
+        }
+        if !self.finished {
+            if let Some(segment) = self.segments.last() {
+                let segment = segment.clone();
+                self.emit(ProfileEvent::SegmentEnd(segment));
+            }
+            self.finished = true;
+        }
+        match self.sink.as_mut() {
+            Some(sink) => sink.flush(),
+            None => Ok(()),
+        }
+    }
+}
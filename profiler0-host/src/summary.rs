@@ -0,0 +1,199 @@
+use crate::render::percentage;
+use crate::report::total_cycles;
+use crate::{CycleTracer, MarkdownRenderer};
+use std::collections::BTreeMap;
+use std::io::{BufRead, Write};
+
+#[derive(Clone, Copy, Default)]
+pub struct BaselineRecord {
+    pub num_cycles: u64,
+    pub num_instructions: u64,
+}
+
+// the top-level timers of a previous run, saved to compare against
+#[derive(Default)]
+pub struct Baseline {
+    pub timers: BTreeMap<String, BaselineRecord>,
+}
+
+impl Baseline {
+    // one line per timer: cycles, instructions and the name, separated by tabs
+    pub fn write<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        for (name, record) in self.timers.iter() {
+            writeln!(
+                writer,
+                "{}\t{}\t{}",
+                record.num_cycles, record.num_instructions, name
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn read<R: BufRead>(reader: R) -> std::io::Result<Self> {
+        let invalid = |line: &str| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("invalid baseline line: {}", line),
+            )
+        };
+
+        let mut baseline = Baseline::default();
+        for line in reader.lines() {
+            let line = line?;
+            if line.is_empty() {
+                continue;
+            }
+            let fields = line.splitn(3, '\t').collect::<Vec<&str>>();
+            if fields.len() != 3 {
+                return Err(invalid(&line));
+            }
+            let (num_cycles, num_instructions, name) = (fields[0], fields[1], fields[2]);
+            baseline.timers.insert(
+                name.to_string(),
+                BaselineRecord {
+                    num_cycles: num_cycles.parse().map_err(|_| invalid(&line))?,
+                    num_instructions: num_instructions.parse().map_err(|_| invalid(&line))?,
+                },
+            );
+        }
+        Ok(baseline)
+    }
+}
+
+fn format_delta(current: u64, baseline: u64) -> String {
+    let delta = current as i64 - baseline as i64;
+    if delta == 0 {
+        "0".to_string()
+    } else {
+        format!(
+            "{:+} ({:+.2}%)",
+            delta,
+            delta as f64 * 100.0 / baseline.max(1) as f64
+        )
+    }
+}
+
+impl CycleTracer {
+    // timers with the same name at the top level, such as those in a loop, are added up
+    pub fn baseline(&self) -> Baseline {
+        let mut baseline = Baseline::default();
+        for node in self.timer_tree().iter() {
+            let record = baseline.timers.entry(node.record.name.clone()).or_default();
+            record.num_cycles += node.record.num_cycles as u64;
+            record.num_instructions += node.record.num_instructions as u64;
+        }
+        baseline
+    }
+}
+
+impl MarkdownRenderer {
+    pub fn render_summary(
+        &self,
+        tracer: &CycleTracer,
+        baseline: Option<&Baseline>,
+        out: &mut dyn Write,
+    ) -> std::io::Result<()> {
+        let current = tracer.baseline();
+        let total = BaselineRecord {
+            num_cycles: total_cycles(&tracer.timer_tree()),
+            num_instructions: current
+                .timers
+                .values()
+                .map(|record| record.num_instructions)
+                .sum(),
+        };
+
+        match baseline {
+            Some(_) => {
+                writeln!(
+                    out,
+                    "| Timer | Cycles | Δ cycles | Instructions | Δ instructions | Share |"
+                )?;
+                writeln!(out, "|---|---:|---:|---:|---:|---:|")?;
+            }
+            None => {
+                writeln!(out, "| Timer | Cycles | Instructions | Share |")?;
+                writeln!(out, "|---|---:|---:|---:|")?;
+            }
+        }
+
+        let write_row = |out: &mut dyn Write,
+                         name: &str,
+                         record: Option<&BaselineRecord>,
+                         before: Option<&BaselineRecord>|
+         -> std::io::Result<()> {
+            let record = record.copied().unwrap_or_default();
+            let share = format!("{:.2}%", percentage(record.num_cycles, total.num_cycles));
+            match baseline {
+                Some(_) => {
+                    let (cycles_delta, instructions_delta) = match before {
+                        Some(before) => (
+                            format_delta(record.num_cycles, before.num_cycles),
+                            format_delta(record.num_instructions, before.num_instructions),
+                        ),
+                        None => ("new".to_string(), "new".to_string()),
+                    };
+                    writeln!(
+                        out,
+                        "| {} | {} | {} | {} | {} | {} |",
+                        name,
+                        record.num_cycles,
+                        cycles_delta,
+                        record.num_instructions,
+                        instructions_delta,
+                        share,
+                    )
+                }
+                None => writeln!(
+                    out,
+                    "| {} | {} | {} | {} |",
+                    name, record.num_cycles, record.num_instructions, share
+                ),
+            }
+        };
+
+        // the timers in the order they first finish
+        let mut names: Vec<String> = Vec::new();
+        for node in tracer.timer_tree().iter() {
+            if !names.contains(&node.record.name) {
+                names.push(node.record.name.clone());
+            }
+        }
+        for name in names.iter() {
+            write_row(
+                out,
+                &name.replace('|', "\\|"),
+                current.timers.get(name),
+                baseline.and_then(|baseline| baseline.timers.get(name)),
+            )?;
+        }
+
+        if let Some(baseline) = baseline {
+            for (name, _) in baseline
+                .timers
+                .iter()
+                .filter(|(name, _)| !current.timers.contains_key(*name))
+            {
+                writeln!(
+                    out,
+                    "| ~~{}~~ | removed | | | | |",
+                    name.replace('|', "\\|")
+                )?;
+            }
+        }
+
+        let total_before = baseline.map(|baseline| BaselineRecord {
+            num_cycles: baseline
+                .timers
+                .values()
+                .map(|record| record.num_cycles)
+                .sum(),
+            num_instructions: baseline
+                .timers
+                .values()
+                .map(|record| record.num_instructions)
+                .sum(),
+        });
+        write_row(out, "**Total**", Some(&total), total_before.as_ref())
+    }
+}
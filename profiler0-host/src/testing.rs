@@ -0,0 +1,256 @@
+use crate::{CycleTracer, CATEGORY_SEPARATOR, ECALL, INTERNED_NAME_FLAG, PROTOCOL_VERSION};
+use risc0_zkvm::TraceEvent;
+
+pub const NOP: u32 = 0x00000013;
+// `sw a1, 0(a0)`
+pub const SW: u32 = 0x00b52023;
+// `lw a1, 0(a0)`
+pub const LW: u32 = 0x00052583;
+
+// generates the trace that the zkVM would send for a guest using `l2r0-profiler-guest`, so that the cycle tracer
+// can be tested without executing a guest
+//
+// every instruction takes one cycle unless `stall` adds more, and `cycle` and `pc` are those of the next instruction
+pub struct TraceBuilder {
+    pub events: Vec<TraceEvent>,
+    pub cycle: u32,
+    pub pc: u32,
+    pub trace_msg_channel: u32,
+    pub trace_msg_len_channel: u32,
+    pub trace_signal_channel: u32,
+    pub trace_span_channel: u32,
+}
+
+impl Default for TraceBuilder {
+    fn default() -> Self {
+        Self {
+            events: Vec::new(),
+            cycle: 0,
+            pc: 0x0020_0800,
+            trace_msg_channel: 0x0030_0104,
+            trace_msg_len_channel: 0x0030_0304,
+            trace_signal_channel: 0x0030_0308,
+            trace_span_channel: 0x0030_030c,
+        }
+    }
+}
+
+impl TraceBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn instruction(&mut self, insn: u32) -> &mut Self {
+        self.events.push(TraceEvent::InstructionStart {
+            cycle: self.cycle,
+            pc: self.pc,
+            insn,
+        });
+        self.cycle += 1;
+        self.pc += 4;
+        self
+    }
+
+    pub fn nops(&mut self, n: u32) -> &mut Self {
+        for _ in 0..n {
+            self.instruction(NOP);
+        }
+        self
+    }
+
+    // the last instruction takes `cycles` more cycles, like one that pages in memory
+    pub fn stall(&mut self, cycles: u32) -> &mut Self {
+        self.cycle += cycles;
+        self
+    }
+
+    // the next instruction is the first one of the next segment
+    pub fn next_segment(&mut self) -> &mut Self {
+        self.cycle = ((self.cycle >> 20) + 1) << 20;
+        self
+    }
+
+    pub fn jump_to(&mut self, pc: u32) -> &mut Self {
+        self.pc = pc;
+        self
+    }
+
+    pub fn register(&mut self, idx: usize, value: u32) -> &mut Self {
+        self.events.push(TraceEvent::RegisterSet { idx, value });
+        self
+    }
+
+    // one `sw` for every word, so a store across a page boundary touches both pages
+    pub fn store(&mut self, addr: u32, bytes: &[u8]) -> &mut Self {
+        for (i, word) in bytes.chunks(4).enumerate() {
+            self.instruction(SW);
+            self.events.push(TraceEvent::MemorySet {
+                addr: addr + i as u32 * 4,
+                region: word.to_vec(),
+            });
+        }
+        self
+    }
+
+    pub fn store_u32(&mut self, addr: u32, value: u32) -> &mut Self {
+        self.store(addr, &value.to_le_bytes())
+    }
+
+    pub fn load(&mut self, addr: u32) -> &mut Self {
+        self.register(10, addr);
+        self.instruction(LW)
+    }
+
+    // the kind of the ecall goes to t0, see `decode_syscall`
+    pub fn ecall(&mut self, kind: u32) -> &mut Self {
+        self.register(5, kind);
+        self.instruction(ECALL)
+    }
+
+    // the sequence of `init_trace_logger`
+    pub fn handshake(&mut self) -> &mut Self {
+        self.handshake_with_version(PROTOCOL_VERSION)
+    }
+
+    pub fn handshake_with_version(&mut self, version: u32) -> &mut Self {
+        self.handshake_start();
+        self.la(self.trace_span_channel);
+        // li x0, <version>
+        self.instruction((version << 20) | 0x013);
+        self.instruction(NOP)
+    }
+
+    // the sequence of guests before the protocol has a version
+    pub fn handshake_without_version(&mut self) -> &mut Self {
+        self.handshake_start();
+        self.la(self.trace_span_channel);
+        self.instruction(NOP)
+    }
+
+    // the sequence of guests that do not have the span channel
+    pub fn handshake_without_spans(&mut self) -> &mut Self {
+        self.handshake_start();
+        self.instruction(NOP)
+    }
+
+    fn handshake_start(&mut self) {
+        self.instruction(NOP);
+        // li x0, 0xCDCDCDCD
+        self.instruction(0xcdcdd037);
+        self.instruction(0xdcd00013);
+        self.la(self.trace_msg_channel);
+        self.la(self.trace_msg_len_channel);
+        self.la(self.trace_signal_channel);
+    }
+
+    // `la x0, addr`, which is an `auipc` and an `addi` with the offset split between the two
+    pub fn la(&mut self, addr: u32) -> &mut Self {
+        let offset = addr.wrapping_sub(self.pc);
+        let hi = offset.wrapping_add(0x800) & 0xfffff000;
+        let lo = offset.wrapping_sub(hi) & 0xfff;
+        self.instruction(hi | 0x017);
+        self.instruction((lo << 20) | 0x013)
+    }
+
+    pub fn start_timer(&mut self, name: &str) -> &mut Self {
+        self.send_name(name.as_bytes());
+        self.store_u32(self.trace_msg_len_channel, name.len() as u32)
+    }
+
+    pub fn stop_timer(&mut self) -> &mut Self {
+        self.store_u32(self.trace_signal_channel, 0)
+    }
+
+    pub fn stop_start_timer(&mut self, name: &str) -> &mut Self {
+        self.stop_timer();
+        self.start_timer(name)
+    }
+
+    // the guest only sends the address of the name, which the cycle tracer looks up in the guest ELF
+    pub fn start_interned_timer(&mut self, addr: u32, len: u32) -> &mut Self {
+        self.store_u32(self.trace_msg_channel, addr);
+        self.store_u32(self.trace_msg_len_channel, len | INTERNED_NAME_FLAG)
+    }
+
+    pub fn start_category_timer(&mut self, category: &str, name: &str) -> &mut Self {
+        let msg = format!("{}{}{}", category, CATEGORY_SEPARATOR, name);
+        self.send_name(msg.as_bytes());
+        self.store_u32(self.trace_msg_len_channel, msg.len() as u32)
+    }
+
+    pub fn stop_category_timer(&mut self, category: &str) -> &mut Self {
+        self.send_name(category.as_bytes());
+        self.store_u32(self.trace_signal_channel, category.len() as u32)
+    }
+
+    pub fn start_span(&mut self, id: u32, name: &str) -> &mut Self {
+        self.send_name(name.as_bytes());
+        self.store_u32(self.trace_span_channel, id);
+        self.store_u32(self.trace_span_channel + 4, name.len() as u32)
+    }
+
+    pub fn stop_span(&mut self, id: u32) -> &mut Self {
+        self.store_u32(self.trace_span_channel + 8, id)
+    }
+
+    fn send_name(&mut self, name: &[u8]) {
+        self.store(self.trace_msg_channel, name);
+    }
+
+    pub fn events(&self) -> &[TraceEvent] {
+        &self.events
+    }
+
+    pub fn feed(&self, tracer: &mut CycleTracer) {
+        for event in self.events.iter() {
+            tracer.handle_event(event.clone());
+        }
+    }
+
+    // a new cycle tracer with the whole trace, including the end of the last segment
+    pub fn run(&self) -> CycleTracer {
+        let mut tracer = CycleTracer::default();
+        self.feed(&mut tracer);
+        tracer.finish().unwrap();
+        tracer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn la_reaches_addresses_before_and_after_the_pc() {
+        for addr in [0x0020_0a00, 0x0020_0c04, 0x0010_0004, 0x0030_07fc] {
+            let mut builder = TraceBuilder::new();
+            builder.trace_msg_channel = addr;
+            let tracer = builder.handshake().run();
+            assert_eq!(tracer.init_state_machine, 999);
+            assert_eq!(tracer.trace_msg_channel, addr);
+        }
+    }
+
+    #[test]
+    fn store_splits_into_words() {
+        let mut builder = TraceBuilder::new();
+        builder.store(0x400, &[1, 2, 3, 4, 5, 6]);
+        assert_eq!(builder.events().len(), 4);
+        assert_eq!(
+            builder.events()[3],
+            TraceEvent::MemorySet {
+                addr: 0x404,
+                region: vec![5, 6]
+            }
+        );
+    }
+
+    #[test]
+    fn next_segment_moves_to_the_segment_start() {
+        let mut builder = TraceBuilder::new();
+        builder.nops(3).next_segment();
+        assert_eq!(builder.cycle, 1 << 20);
+        builder.next_segment();
+        assert_eq!(builder.cycle, 2 << 20);
+    }
+}
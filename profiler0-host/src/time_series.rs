@@ -0,0 +1,46 @@
+use crate::CycleTracer;
+use std::io::Write;
+
+#[derive(Clone, Copy)]
+pub enum SampleInterval {
+    Instructions(u32),
+    Cycles(u32),
+}
+
+pub struct Sample {
+    pub cycle: u32,
+    pub num_instructions: u32,
+    pub num_pages_loaded: u32,
+    pub num_pages_dirtied: u32,
+    pub timer_path: String,
+}
+
+fn escape_csv(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+impl CycleTracer {
+    pub fn write_time_series_csv<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        writeln!(
+            writer,
+            "cycle,instructions,pages_loaded,pages_dirtied,segment,timer_path"
+        )?;
+        for sample in self.samples.iter() {
+            writeln!(
+                writer,
+                "{},{},{},{},{},{}",
+                sample.cycle,
+                sample.num_instructions,
+                sample.num_pages_loaded,
+                sample.num_pages_dirtied,
+                sample.cycle >> 20,
+                escape_csv(&sample.timer_path),
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,1094 @@
+use l2r0_profiler_host::testing::TraceBuilder;
+use l2r0_profiler_host::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+fn page_of(addr: u32) -> u32 {
+    addr >> 10
+}
+
+#[test]
+fn handshake_finds_the_channels() {
+    let builder = {
+        let mut builder = TraceBuilder::new();
+        builder.handshake();
+        builder
+    };
+    let tracer = builder.run();
+    assert_eq!(tracer.init_state_machine, 999);
+    assert_eq!(tracer.trace_msg_channel, builder.trace_msg_channel);
+    assert_eq!(tracer.trace_msg_len_channel, builder.trace_msg_len_channel);
+    assert_eq!(tracer.trace_cycle_channel, builder.trace_signal_channel);
+    assert_eq!(tracer.trace_span_channel, builder.trace_span_channel);
+}
+
+#[test]
+fn handshake_after_nop_padding() {
+    // an odd number of nops must not leave the state machine halfway
+    for padding in 0..4 {
+        let mut builder = TraceBuilder::new();
+        builder.nops(padding).handshake();
+        let tracer = builder.run();
+        assert_eq!(tracer.init_state_machine, 999);
+        assert_eq!(tracer.trace_msg_channel, builder.trace_msg_channel);
+    }
+}
+
+#[test]
+fn handshake_with_channels_before_the_code() {
+    // the `auipc` has a negative offset
+    let mut builder = TraceBuilder::new();
+    builder.trace_msg_channel = 0x0010_0004;
+    builder.trace_span_channel = 0x0000_0400;
+    builder.handshake();
+    let tracer = builder.run();
+    assert_eq!(tracer.init_state_machine, 999);
+    assert_eq!(tracer.trace_msg_channel, 0x0010_0004);
+    assert_eq!(tracer.trace_span_channel, 0x0000_0400);
+}
+
+#[test]
+fn handshake_without_spans_from_older_guests() {
+    let mut builder = TraceBuilder::new();
+    builder.handshake_without_spans();
+    let tracer = builder.run();
+    assert_eq!(tracer.init_state_machine, 999);
+    assert_eq!(tracer.trace_cycle_channel, builder.trace_signal_channel);
+    assert_eq!(tracer.trace_span_channel, 0);
+}
+
+#[test]
+fn interrupted_handshake_starts_over() {
+    let mut builder = TraceBuilder::new();
+    builder
+        .nops(1)
+        .instruction(0xcdcdd037)
+        .nops(1)
+        .instruction(0xdcd00013);
+    let tracer = builder.run();
+    assert_eq!(tracer.init_state_machine, 0);
+
+    builder.handshake();
+    let tracer = builder.run();
+    assert_eq!(tracer.init_state_machine, 999);
+    assert_eq!(tracer.trace_msg_channel, builder.trace_msg_channel);
+}
+
+#[test]
+fn nested_timers() {
+    let mut builder = TraceBuilder::new();
+    builder
+        .handshake()
+        .start_timer("Total")
+        .nops(10)
+        .start_timer("Load")
+        .nops(100)
+        .stop_start_timer("Compute")
+        .nops(200)
+        .stop_timer()
+        .stop_timer();
+    let tracer = builder.run();
+
+    let roots = tracer.timer_tree();
+    assert_eq!(roots.len(), 1);
+    let total = &roots[0];
+    assert_eq!(total.record.name, "Total");
+    assert_eq!(total.children.len(), 2);
+    assert_eq!(total.children[0].record.name, "Load");
+    assert_eq!(total.children[0].path, "Total/Load");
+    assert_eq!(total.children[1].record.name, "Compute");
+    assert_eq!(total.children[1].depth, 1);
+    assert!(tracer.pending_records.is_empty());
+
+    // the nops, and the writes to the channels of the inner timers
+    let load = total.children[0].record;
+    assert!(load.num_instructions >= 100 && load.num_instructions < 110);
+    assert_eq!(load.num_cycles, load.num_instructions);
+    assert!(total.record.num_cycles > 310);
+    assert_eq!(
+        total.self_cycles,
+        total.record.num_cycles
+            - total.children[0].record.num_cycles
+            - total.children[1].record.num_cycles
+    );
+}
+
+#[test]
+fn writes_to_other_channels_are_not_timers() {
+    let mut tracer = CycleTracer {
+        init_state_machine: 999,
+        trace_msg_channel: 0x1_0000,
+        trace_msg_len_channel: 0x2_0000,
+        trace_cycle_channel: 0x2_0004,
+        ..Default::default()
+    };
+    let mut builder = TraceBuilder::new();
+    builder.nops(3).start_timer("Lost").stop_timer();
+    builder.feed(&mut tracer);
+    assert!(tracer.finished_records.is_empty());
+}
+
+#[test]
+fn categories_are_nested_separately() {
+    let mut builder = TraceBuilder::new();
+    builder
+        .handshake()
+        .start_timer("App")
+        .start_category_timer("crypto", "Hash")
+        .nops(50)
+        // the application timer stops while the category timer is still running
+        .stop_timer()
+        .nops(50)
+        .stop_category_timer("crypto");
+    let tracer = builder.run();
+
+    assert_eq!(tracer.categories(), vec!["crypto".to_string()]);
+    let app = tracer.timer_tree();
+    assert_eq!(app.len(), 1);
+    assert_eq!(app[0].record.name, "App");
+    assert!(app[0].children.is_empty());
+
+    let crypto = tracer.category_timer_tree("crypto");
+    assert_eq!(crypto.len(), 1);
+    assert_eq!(crypto[0].record.name, "Hash");
+    assert_eq!(crypto[0].depth, 0);
+    assert!(crypto[0].record.num_instructions > app[0].record.num_instructions);
+}
+
+#[test]
+fn stopping_a_category_without_timers_is_a_warning() {
+    let mut builder = TraceBuilder::new();
+    builder.handshake().start_category_timer("crypto", "Hash");
+    for _ in 0..3 {
+        builder.nops(10).stop_category_timer("Crypto");
+    }
+    builder.stop_category_timer("crypto").stop_timer();
+    let tracer = builder.run();
+
+    let crypto = tracer.category_timer_tree("crypto");
+    assert_eq!(crypto.len(), 1);
+    assert_eq!(crypto[0].record.name, "Hash");
+    assert_eq!(
+        tracer.warnings,
+        vec![
+            "the guest stops a timer of category \"Crypto\" when none is open, so the stop is ignored".to_string(),
+            "the guest stops a timer when none is open, so the stop is ignored".to_string(),
+        ]
+    );
+}
+
+#[test]
+fn spans_can_overlap() {
+    let mut builder = TraceBuilder::new();
+    builder
+        .handshake()
+        .start_span(1, "Stage A")
+        .nops(20)
+        .start_span(2, "Stage B")
+        .nops(20)
+        .stop_span(1)
+        .nops(20)
+        .stop_span(2);
+    let tracer = builder.run();
+
+    assert!(tracer.pending_spans.is_empty());
+    assert_eq!(tracer.finished_spans.len(), 2);
+    let a = &tracer.finished_spans[0];
+    let b = &tracer.finished_spans[1];
+    assert_eq!((a.id, a.name.as_str()), (1, "Stage A"));
+    assert_eq!((b.id, b.name.as_str()), (2, "Stage B"));
+    assert!(a.start_cycle < b.start_cycle);
+    assert!(a.start_cycle + a.num_cycles < b.start_cycle + b.num_cycles);
+    assert!(a.start_cycle + a.num_cycles > b.start_cycle);
+}
+
+#[test]
+fn spans_are_ignored_by_older_guests() {
+    let mut builder = TraceBuilder::new();
+    builder
+        .handshake_without_spans()
+        .start_span(1, "Stage A")
+        .stop_span(1);
+    let tracer = builder.run();
+    assert!(tracer.pending_spans.is_empty());
+    assert!(tracer.finished_spans.is_empty());
+}
+
+#[test]
+fn interned_names_without_the_elf() {
+    let mut builder = TraceBuilder::new();
+    builder
+        .handshake()
+        .start_interned_timer(0x0001_2340, 5)
+        .stop_timer();
+    let tracer = builder.run();
+    assert_eq!(tracer.finished_records[0].name, "<name at 0x012340>");
+}
+
+#[test]
+fn significant_cycles_are_attributed_to_the_timer() {
+    let mut builder = TraceBuilder::new();
+    builder.handshake().start_timer("Total").start_timer("Slow");
+    let slow_pc = builder.pc;
+    builder
+        .load(0x0040_0000)
+        .stall(2000)
+        .nops(1)
+        .stop_timer()
+        .stop_timer();
+    let tracer = builder.run();
+
+    let significant_cycles = tracer
+        .significant_cycles
+        .iter()
+        .filter(|significant_cycle| significant_cycle.pc == slow_pc)
+        .collect::<Vec<_>>();
+    assert_eq!(significant_cycles.len(), 1);
+    let significant_cycle = significant_cycles[0];
+    assert_eq!(significant_cycle.timer_path, "Total/Slow");
+    assert_eq!(
+        significant_cycle.current_cycle - significant_cycle.previous_cycle,
+        2001
+    );
+    assert!(significant_cycle
+        .latest_loaded_pages
+        .contains(&page_of(0x0040_0000)));
+
+    let hotspots = tracer.hotspots();
+    assert_eq!(hotspots[0].pc, slow_pc);
+    assert_eq!(hotspots[0].extra_cycles, 2000);
+
+    let roots = tracer.timer_tree();
+    let slow = &roots[0].children[0];
+    assert_eq!(slow.record.name, "Slow");
+    assert_eq!(
+        slow.record.end_significant_cycles - slow.record.start_significant_cycles,
+        1
+    );
+    assert_eq!(slow.significant_cycles.len(), 1);
+}
+
+#[test]
+fn store_across_a_page_boundary_dirties_both_pages() {
+    let mut builder = TraceBuilder::new();
+    builder
+        .handshake()
+        .start_timer("Write")
+        .store(0x0050_03fc, &[0u8; 8])
+        .nops(1)
+        .stop_timer();
+    let tracer = builder.run();
+
+    for page in [page_of(0x0050_03fc), page_of(0x0050_0400)] {
+        let record = &tracer.page_records[&page];
+        assert_eq!(record.num_segments_loaded, 1);
+        assert_eq!(record.num_segments_dirtied, 1);
+        assert_eq!(record.timers["Write"], 1);
+    }
+    assert!(tracer.page_accessed.contains(page_of(0x0050_0400)));
+    assert!(!tracer.page_accessed.contains(page_of(0x0050_0800)));
+}
+
+#[test]
+fn pages_read_before_are_not_loaded_again() {
+    let mut builder = TraceBuilder::new();
+    builder
+        .handshake()
+        .load(0x0060_0000)
+        .nops(1)
+        .store_u32(0x0060_0010, 1)
+        .nops(1);
+    let tracer = builder.run();
+
+    let record = &tracer.page_records[&page_of(0x0060_0000)];
+    assert_eq!(record.num_segments_loaded, 1);
+    assert_eq!(record.num_segments_dirtied, 1);
+}
+
+#[test]
+fn segment_boundaries() {
+    let mut builder = TraceBuilder::new();
+    builder
+        .handshake()
+        .start_timer("Total")
+        .store_u32(0x0050_0000, 1)
+        .next_segment()
+        .store_u32(0x0050_0000, 2)
+        .next_segment()
+        .nops(10)
+        .stop_timer();
+    let tracer = builder.run();
+
+    assert_eq!(tracer.segments.len(), 3);
+    assert_eq!(
+        tracer
+            .segments
+            .iter()
+            .map(|segment| segment.index)
+            .collect::<Vec<_>>(),
+        vec![0, 1, 2]
+    );
+    assert_eq!(tracer.segments[0].boundary_cycles, 0);
+    assert!(tracer.segments[1].boundary_cycles > 1_000_000);
+    assert_eq!(tracer.segments[1].active_timers, vec!["Total".to_string()]);
+    assert_eq!(tracer.segments[1].start_cycle, 1 << 20);
+
+    // the page is dirtied again in the second segment
+    let record = &tracer.page_records[&page_of(0x0050_0000)];
+    assert_eq!(record.num_segments_dirtied, 2);
+
+    // the jump to the next segment is a significant cycle in the segment before
+    assert!(tracer
+        .significant_cycles
+        .iter()
+        .any(|significant_cycle| significant_cycle.first_instruction_new_segment));
+}
+
+#[test]
+fn syscalls_are_counted_in_the_timer() {
+    let mut builder = TraceBuilder::new();
+    builder
+        .handshake()
+        .start_timer("Hash")
+        .ecall(3)
+        .stall(100)
+        .ecall(3)
+        .stall(100)
+        .nops(1)
+        .stop_timer();
+    let tracer = builder.run();
+
+    let sha = &tracer.finished_records[0].syscalls["sys_sha"];
+    assert_eq!(sha.num_calls, 2);
+    assert_eq!(sha.num_cycles, 202);
+}
+
+#[test]
+fn events_go_to_the_sink_in_order() {
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let mut tracer = CycleTracer::default();
+    let events_in_sink = events.clone();
+    tracer.sink = Some(Box::new(move |event: &ProfileEvent| {
+        let kind = match event {
+            ProfileEvent::TimerStart { name, .. } => format!("start {}", name),
+            ProfileEvent::TimerStop(record) => format!("stop {}", record.name),
+            ProfileEvent::SignificantCycle(_) => "significant".to_string(),
+            ProfileEvent::SegmentStart { index, .. } => format!("segment {}", index),
+            ProfileEvent::SegmentEnd(segment) => format!("end {}", segment.index),
+            ProfileEvent::SessionStart { label } => format!("session {}", label),
+            ProfileEvent::SessionEnd => "session end".to_string(),
+        };
+        events_in_sink.borrow_mut().push(kind);
+    }));
+    tracer.retain_records = false;
+
+    let mut builder = TraceBuilder::new();
+    builder
+        .handshake()
+        .start_timer("Total")
+        .next_segment()
+        .nops(1)
+        .stop_timer();
+    builder.feed(&mut tracer);
+    tracer.finish().unwrap();
+
+    assert!(tracer.finished_records.is_empty());
+    assert_eq!(tracer.segments.len(), 1);
+    assert_eq!(
+        *events.borrow(),
+        vec![
+            "segment 0",
+            "start Total",
+            "end 0",
+            "segment 1",
+            "significant",
+            "stop Total",
+            "end 1"
+        ]
+    );
+}
+
+#[test]
+fn sessions_start_over() {
+    let mut first = TraceBuilder::new();
+    first.handshake().start_timer("First").nops(5).stop_timer();
+    let mut second = TraceBuilder::new();
+    second.trace_msg_channel = 0x0031_0000;
+    second
+        .handshake()
+        .start_timer("Second")
+        .nops(5)
+        .stop_timer();
+
+    let mut tracer = CycleTracer::default();
+    tracer.begin_session("a");
+    first.feed(&mut tracer);
+    tracer.begin_session("b");
+    second.feed(&mut tracer);
+    tracer.end_session();
+
+    assert_eq!(tracer.sessions.len(), 2);
+    assert_eq!(
+        tracer.session("a").unwrap().finished_records[0].name,
+        "First"
+    );
+    let b = tracer.session("b").unwrap();
+    assert_eq!(b.finished_records[0].name, "Second");
+    assert_eq!(b.trace_msg_channel, 0x0031_0000);
+}
+
+#[test]
+fn sessions_are_marked_in_the_sink() {
+    let mut first = TraceBuilder::new();
+    first.handshake().start_timer("First").nops(5).stop_timer();
+    let mut second = TraceBuilder::new();
+    second
+        .handshake()
+        .start_timer("Second")
+        .nops(5)
+        .stop_timer();
+
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let events_in_sink = events.clone();
+    let mut tracer = CycleTracer {
+        sink: Some(Box::new(move |event: &ProfileEvent| {
+            let kind = match event {
+                ProfileEvent::SegmentEnd(segment) => format!("end {}", segment.index),
+                ProfileEvent::SessionStart { label } => format!("session {}", label),
+                ProfileEvent::SessionEnd => "session end".to_string(),
+                _ => return,
+            };
+            events_in_sink.borrow_mut().push(kind);
+        })),
+        ..Default::default()
+    };
+    tracer.begin_session("a");
+    first.feed(&mut tracer);
+    // the host may finish before the session ends, which finishes again
+    tracer.finish().unwrap();
+    tracer.begin_session("b");
+    second.feed(&mut tracer);
+    tracer.end_session();
+    tracer.finish().unwrap();
+
+    assert_eq!(
+        *events.borrow(),
+        vec![
+            "session a",
+            "end 0",
+            "session end",
+            "session b",
+            "end 0",
+            "session end"
+        ]
+    );
+
+    let mut sink = WriterSink::new(Vec::new());
+    sink.handle(&ProfileEvent::SessionStart {
+        label: "Verify".to_string(),
+    });
+    sink.handle(&ProfileEvent::SessionEnd);
+    assert_eq!(
+        String::from_utf8(sink.writer).unwrap(),
+        "session_start\tVerify\nsession_end\n"
+    );
+}
+
+#[test]
+fn replay_gives_the_same_profile() {
+    let mut builder = TraceBuilder::new();
+    builder
+        .handshake()
+        .start_timer("Total")
+        .load(0x0040_0000)
+        .stall(1500)
+        .store(0x0050_03fc, &[1u8; 8])
+        .jump_to(0x0020_0000)
+        .nops(3)
+        .stop_timer();
+
+    let mut recorder = TraceRecorder::new(Vec::new()).unwrap();
+    for event in builder.events() {
+        recorder.record(event).unwrap();
+    }
+    let bytes = recorder.finish().unwrap();
+
+    let replayed = TraceReplayer::new(&bytes[..])
+        .unwrap()
+        .collect::<std::io::Result<Vec<_>>>()
+        .unwrap();
+    assert_eq!(replayed, builder.events());
+
+    let mut tracer = CycleTracer::default();
+    tracer.replay(&bytes[..]).unwrap();
+    let expected = builder.run();
+    assert_eq!(tracer.num_instructions, expected.num_instructions);
+    assert_eq!(
+        tracer.finished_records[0].num_cycles,
+        expected.finished_records[0].num_cycles
+    );
+    assert_eq!(
+        tracer.significant_cycles.len(),
+        expected.significant_cycles.len()
+    );
+}
+
+#[test]
+fn replay_rejects_other_files() {
+    assert!(TraceReplayer::new(&b"not a trace"[..]).is_err());
+}
+
+#[test]
+fn renderers_show_the_timers() {
+    let mut builder = TraceBuilder::new();
+    builder
+        .handshake()
+        .start_timer("Total")
+        .start_timer("A | B")
+        .nops(10)
+        .stop_timer()
+        .stop_timer();
+    let tracer = builder.run();
+
+    let mut text = Vec::new();
+    PlainTextRenderer
+        .render_timers(&tracer, &ReportOptions::default(), &mut text)
+        .unwrap();
+    let text = String::from_utf8(text).unwrap();
+    assert!(text.contains("Total"));
+    assert!(text.contains("A | B"));
+    assert!(!text.contains('\x1b'));
+
+    let mut markdown = Vec::new();
+    MarkdownRenderer
+        .render_segments(&tracer, &mut markdown)
+        .unwrap();
+    MarkdownRenderer
+        .render_summary(&tracer, Some(&tracer.baseline()), &mut markdown)
+        .unwrap();
+    let markdown = String::from_utf8(markdown).unwrap();
+    assert!(markdown.contains("Total"));
+
+    let mut html = Vec::new();
+    tracer
+        .write_html(&ReportOptions::default(), &mut html)
+        .unwrap();
+    let html = String::from_utf8(html).unwrap();
+    assert!(html.contains("A | B"));
+
+    // the sections of the page can also be written on their own
+    let mut segments = Vec::new();
+    HtmlRenderer
+        .render_segments(&tracer, &mut segments)
+        .unwrap();
+    let segments = String::from_utf8(segments).unwrap();
+    assert!(segments.starts_with("<h2>Segments</h2>"));
+    assert!(html.contains(&segments));
+
+    // the page has several sortable tables, so a header sorts by its position in its own row
+    assert!(html.matches("<table class=\"sortable\">").count() > 1);
+    assert!(html.contains("var column = th.cellIndex;"));
+    assert!(!html.contains("function (th, column)"));
+}
+
+#[test]
+fn report_options_filter_the_timers() {
+    let mut builder = TraceBuilder::new();
+    builder
+        .handshake()
+        .start_timer("Total")
+        .start_timer("Load")
+        .nops(10)
+        .stop_start_timer("Hash")
+        .start_timer("Inner")
+        .nops(10)
+        .stop_timer()
+        .stop_timer()
+        .stop_timer();
+    let tracer = builder.run();
+
+    let options = ReportOptions {
+        max_depth: Some(2),
+        ..Default::default()
+    };
+    let roots = tracer.filtered_timer_tree(&options);
+    assert_eq!(roots[0].children.len(), 2);
+    assert!(roots[0]
+        .children
+        .iter()
+        .all(|child| child.children.is_empty()));
+
+    let options = ReportOptions {
+        exclude: vec![NamePattern::glob("Hash")],
+        ..Default::default()
+    };
+    let roots = tracer.filtered_timer_tree(&options);
+    assert_eq!(roots[0].children.len(), 1);
+    assert_eq!(roots[0].children[0].record.name, "Load");
+}
+
+#[test]
+fn glob_patterns_match_names_and_paths() {
+    let long_name = "a".repeat(100);
+    let mut builder = TraceBuilder::new();
+    builder
+        .handshake()
+        .start_timer("Total")
+        .start_timer("Load data")
+        .start_timer("Hash")
+        .stop_timer()
+        .stop_timer()
+        .start_timer(&long_name)
+        .stop_timer()
+        .stop_timer();
+    let tracer = builder.run();
+    let roots = tracer.timer_tree();
+    let load = &roots[0].children[0];
+    let hash = &load.children[0];
+    let long = &roots[0].children[1];
+
+    assert!(NamePattern::glob("Load*").matches(load));
+    assert!(NamePattern::glob("L??d *").matches(load));
+    assert!(NamePattern::glob("*data").matches(load));
+    assert!(!NamePattern::glob("Load").matches(load));
+    assert!(NamePattern::glob("Total/*/Hash").matches(hash));
+    assert!(NamePattern::glob("*").matches(hash));
+    assert!(NamePattern::glob("**H*s*h**").matches(hash));
+    assert!(!NamePattern::glob("*Hash?").matches(hash));
+
+    // every `*` could take any part of the name, which must not be tried one combination at a time
+    assert!(NamePattern::glob(&"a*".repeat(20)).matches(long));
+    assert!(!NamePattern::glob(&format!("{}b", "a*".repeat(20))).matches(long));
+}
+
+fn guest_elf_with_channels(builder: &TraceBuilder, with_spans: bool) -> GuestElf {
+    let mut symbols = std::collections::BTreeMap::new();
+    symbols.insert("TRACE_MSG_CHANNEL".to_string(), builder.trace_msg_channel);
+    symbols.insert(
+        "TRACE_MSG_LEN_CHANNEL".to_string(),
+        builder.trace_msg_len_channel,
+    );
+    symbols.insert(
+        "TRACE_SIGNAL_CHANNEL".to_string(),
+        builder.trace_signal_channel,
+    );
+    if with_spans {
+        symbols.insert("TRACE_SPAN_CHANNEL".to_string(), builder.trace_span_channel);
+    }
+    GuestElf {
+        segments: vec![],
+        symbols,
+    }
+}
+
+#[test]
+fn channels_from_the_elf_without_handshake() {
+    let mut builder = TraceBuilder::new();
+    builder
+        .nops(3)
+        .start_timer("Total")
+        .start_span(7, "Span")
+        .nops(10)
+        .stop_span(7)
+        .stop_timer();
+
+    let mut tracer = CycleTracer {
+        guest_elf: Some(guest_elf_with_channels(&builder, true)),
+        ..Default::default()
+    };
+    assert!(tracer.find_channels());
+    builder.feed(&mut tracer);
+    assert_eq!(tracer.finished_records[0].name, "Total");
+    assert_eq!(tracer.finished_spans[0].name, "Span");
+
+    let mut tracer = CycleTracer {
+        guest_elf: Some(guest_elf_with_channels(&builder, false)),
+        ..Default::default()
+    };
+    assert!(tracer.find_channels());
+    assert_eq!(tracer.trace_span_channel, 0);
+}
+
+#[test]
+fn handshake_is_still_accepted_with_channels_from_the_elf() {
+    let mut builder = TraceBuilder::new();
+    let mut tracer = CycleTracer {
+        guest_elf: Some(guest_elf_with_channels(&builder, true)),
+        ..Default::default()
+    };
+    tracer.find_channels();
+    builder.handshake().start_timer("Total").stop_timer();
+    builder.feed(&mut tracer);
+    assert_eq!(tracer.finished_records[0].name, "Total");
+}
+
+#[test]
+fn stripped_elf_waits_for_the_handshake() {
+    let mut tracer = CycleTracer {
+        guest_elf: Some(GuestElf {
+            segments: vec![],
+            symbols: Default::default(),
+        }),
+        ..Default::default()
+    };
+    assert!(!tracer.find_channels());
+    assert_eq!(tracer.init_state_machine, 0);
+
+    // the channels that were found before belong to another guest
+    let mut tracer = TraceBuilder::new().handshake().run();
+    assert_eq!(tracer.init_state_machine, 999);
+    tracer.guest_elf = Some(GuestElf {
+        segments: vec![],
+        symbols: Default::default(),
+    });
+    assert!(!tracer.find_channels());
+    assert_eq!(tracer.init_state_machine, 0);
+    assert_eq!(tracer.trace_msg_channel, 0);
+    assert_eq!(tracer.protocol_version, None);
+}
+
+#[test]
+fn protocol_version_from_the_handshake() {
+    let mut builder = TraceBuilder::new();
+    builder.handshake().start_timer("Total").stop_timer();
+    let tracer = builder.run();
+    assert_eq!(tracer.protocol_version, Some(PROTOCOL_VERSION));
+    assert!(tracer.warnings.is_empty());
+    assert_eq!(tracer.finished_records[0].name, "Total");
+}
+
+#[test]
+fn older_guests_are_version_1() {
+    let mut builder = TraceBuilder::new();
+    builder
+        .handshake_without_version()
+        .start_span(1, "Span")
+        .stop_span(1);
+    let tracer = builder.run();
+    assert_eq!(tracer.protocol_version, Some(1));
+    assert!(tracer.warnings.is_empty());
+    assert_eq!(tracer.finished_spans[0].name, "Span");
+
+    let mut builder = TraceBuilder::new();
+    builder
+        .handshake_without_spans()
+        .start_timer("Total")
+        .stop_timer();
+    let tracer = builder.run();
+    assert_eq!(tracer.protocol_version, Some(1));
+    assert_eq!(tracer.finished_records[0].name, "Total");
+}
+
+#[test]
+fn newer_guests_are_reported() {
+    let mut builder = TraceBuilder::new();
+    builder
+        .handshake_with_version(PROTOCOL_VERSION + 1)
+        .start_timer("Total")
+        .stop_timer();
+    let tracer = builder.run();
+    assert_eq!(tracer.protocol_version, Some(PROTOCOL_VERSION + 1));
+    assert_eq!(tracer.warnings.len(), 1);
+
+    let mut text = Vec::new();
+    PlainTextRenderer
+        .render_timers(&tracer, &ReportOptions::default(), &mut text)
+        .unwrap();
+    let text = String::from_utf8(text).unwrap();
+    assert!(text.starts_with("Warning: the guest uses version 3"));
+}
+
+#[test]
+fn names_that_do_not_fit_the_layout() {
+    let mut builder = TraceBuilder::new();
+    let (msg, msg_len) = (builder.trace_msg_channel, builder.trace_msg_len_channel);
+    builder
+        .handshake()
+        .store(msg, &[0xff, 0xfe, b'A', b'B'])
+        .store_u32(msg_len, 4)
+        .store_u32(msg_len, 10000)
+        .stop_timer()
+        .stop_timer();
+    let tracer = builder.run();
+    assert_eq!(tracer.finished_records[0].name, "<name of 10000 bytes>");
+    assert_eq!(tracer.finished_records[1].name, "\u{fffd}\u{fffd}AB");
+}
+
+#[test]
+fn protocol_version_from_the_elf() {
+    let builder = TraceBuilder::new();
+    let mut elf = guest_elf_with_channels(&builder, true);
+    let mut tracer = CycleTracer {
+        guest_elf: Some(GuestElf {
+            segments: vec![],
+            symbols: elf.symbols.clone(),
+        }),
+        ..Default::default()
+    };
+    tracer.find_channels();
+    assert_eq!(tracer.protocol_version, Some(1));
+
+    elf.symbols
+        .insert("TRACE_PROTOCOL_VERSION".to_string(), 0x0040_0000);
+    elf.segments
+        .push((0x0040_0000, (PROTOCOL_VERSION + 1).to_le_bytes().to_vec()));
+    let mut tracer = CycleTracer {
+        guest_elf: Some(elf),
+        ..Default::default()
+    };
+    tracer.find_channels();
+    assert_eq!(tracer.protocol_version, Some(PROTOCOL_VERSION + 1));
+    assert_eq!(tracer.warnings.len(), 1);
+}
+
+#[test]
+fn causes_of_significant_cycles() {
+    let mut builder = TraceBuilder::new();
+    builder.handshake().nops(1);
+    let jal_pc = builder.pc;
+    // jal x0, 0
+    builder.instruction(0x0000006f).jump_to(0x0028_0000);
+    builder
+        .instruction(testing::NOP)
+        .stall(PAGE_CYCLES + 10)
+        .ecall(3)
+        .stall(3000)
+        .load(0x0040_0000)
+        .stall(PAGE_CYCLES * 4)
+        .store_u32(0x0050_0000, 1)
+        .next_segment()
+        .nops(1);
+    let tracer = builder.run();
+
+    let causes = tracer
+        .significant_cycles
+        .iter()
+        .map(|significant_cycle| significant_cycle.causes.clone())
+        .collect::<Vec<_>>();
+    assert_eq!(causes.len(), 4);
+
+    assert_eq!(causes[0].len(), 1);
+    match &causes[0][0] {
+        (CycleCause::JumpIntoNewPage { pc, pages, .. }, cycles) => {
+            assert_eq!(*pc, jal_pc);
+            assert_eq!(pages[0], page_of(0x0028_0000));
+            // the pages of the page table above it are also loaded
+            assert_eq!(pages[1], (0x0D00_0000 + pages[0] * 32) >> 10);
+            // which is more than the extra cycles, so all of them go to this cause
+            assert!(pages.len() as u32 * PAGE_CYCLES > PAGE_CYCLES + 10);
+            assert_eq!(*cycles, PAGE_CYCLES + 10);
+        }
+        cause => panic!("unexpected {:?}", cause),
+    }
+
+    assert_eq!(
+        causes[1],
+        vec![(CycleCause::Syscall("sys_sha".to_string()), 3000)]
+    );
+
+    assert_eq!(causes[2][0].0.name(), "data_page_in");
+    assert!(causes[2][0].0.pages().contains(&page_of(0x0040_0000)));
+
+    // the bitmaps are cleared for the new segment before the last instruction is accounted for
+    let last = &causes[3];
+    let dirtied = last
+        .iter()
+        .find(|(cause, _)| cause.name() == "page_dirtied")
+        .unwrap();
+    assert_eq!(dirtied.0.pages()[0], page_of(0x0050_0000));
+    assert_eq!(last[last.len() - 1].0, CycleCause::SegmentBoundary);
+    let significant_cycle = &tracer.significant_cycles[3];
+    assert_eq!(
+        last.iter().map(|(_, cycles)| cycles).sum::<u32>(),
+        significant_cycle.current_cycle - significant_cycle.previous_cycle - 1
+    );
+}
+
+#[test]
+fn causes_in_the_reports() {
+    let mut builder = TraceBuilder::new();
+    builder
+        .handshake()
+        .start_timer("Total")
+        .ecall(3)
+        .stall(3000)
+        .nops(1)
+        .stop_timer();
+    let tracer = builder.run();
+
+    let mut text = Vec::new();
+    PlainTextRenderer
+        .render_timers(&tracer, &ReportOptions::default(), &mut text)
+        .unwrap();
+    let text = String::from_utf8(text).unwrap();
+    assert!(text.contains("takes 3001 cycles: it makes the syscall sys_sha (~3000 cycles)"));
+
+    let mut html = Vec::new();
+    tracer
+        .write_html(&ReportOptions::default(), &mut html)
+        .unwrap();
+    assert!(String::from_utf8(html)
+        .unwrap()
+        .contains("makes the syscall sys_sha (~3000 cycles)"));
+
+    let mut sink = WriterSink::new(Vec::new());
+    sink.handle(&ProfileEvent::SignificantCycle(
+        tracer.significant_cycles[0].clone(),
+    ));
+    let line = String::from_utf8(sink.writer).unwrap();
+    assert!(line.ends_with("\tsyscall:3000\tTotal\n"));
+}
+
+// takes `capacity` bytes and then fails, like a full disk
+struct FullWriter {
+    written: Vec<u8>,
+    capacity: usize,
+    num_writes: usize,
+}
+
+impl std::io::Write for FullWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.num_writes += 1;
+        if self.written.len() + buf.len() > self.capacity {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::WriteZero,
+                "disk full",
+            ));
+        }
+        self.written.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn writer_sink_stops_at_the_first_error() {
+    let mut sink = WriterSink::new(FullWriter {
+        written: Vec::new(),
+        capacity: 32,
+        num_writes: 0,
+    });
+    for cycle in 0..10 {
+        sink.handle(&ProfileEvent::SegmentStart { index: 0, cycle });
+    }
+    assert!(sink.writer.written.len() <= 32);
+    let num_writes = sink.writer.num_writes;
+    sink.handle(&ProfileEvent::SegmentStart { index: 1, cycle: 0 });
+    assert_eq!(sink.writer.num_writes, num_writes);
+
+    let mut tracer = CycleTracer {
+        sink: Some(Box::new(sink)),
+        ..Default::default()
+    };
+    let error = tracer.finish().unwrap_err();
+    assert_eq!(error.to_string(), "disk full");
+}
+
+#[test]
+fn streamed_segments_keep_the_boundary_cycles() {
+    let segments = Rc::new(RefCell::new(Vec::new()));
+    let segments_in_sink = segments.clone();
+    let mut tracer = CycleTracer {
+        sink: Some(Box::new(move |event: &ProfileEvent| {
+            if let ProfileEvent::SegmentEnd(segment) = event {
+                segments_in_sink.borrow_mut().push(segment.clone());
+            }
+        })),
+        retain_records: false,
+        ..Default::default()
+    };
+
+    let mut builder = TraceBuilder::new();
+    builder.handshake().nops(10).next_segment().nops(10);
+    builder.feed(&mut tracer);
+    tracer.finish().unwrap();
+
+    let segments = segments.borrow();
+    assert_eq!(segments.len(), 2);
+    assert_eq!(segments[0].boundary_cycles, 0);
+    assert!(segments[1].boundary_cycles > 1_000_000);
+}
+
+#[test]
+fn written_addresses_in_the_reports() {
+    let mut builder = TraceBuilder::new();
+    builder
+        .handshake()
+        .start_timer("Total")
+        .store_u32(0x0050_0010, 1)
+        .stall(2000)
+        .nops(1)
+        .stop_timer();
+    let tracer = builder.run();
+
+    let mut text = Vec::new();
+    PlainTextRenderer
+        .render_timers(&tracer, &ReportOptions::default(), &mut text)
+        .unwrap();
+    assert!(String::from_utf8(text)
+        .unwrap()
+        .contains("that writes to 0x500010 takes 2001 cycles: it"));
+
+    let mut markdown = Vec::new();
+    MarkdownRenderer
+        .render_timers(&tracer, &ReportOptions::default(), &mut markdown)
+        .unwrap();
+    assert!(String::from_utf8(markdown)
+        .unwrap()
+        .contains("that writes to 0x500010"));
+
+    let mut html = Vec::new();
+    tracer
+        .write_html(&ReportOptions::default(), &mut html)
+        .unwrap();
+    let html = String::from_utf8(html).unwrap();
+    assert!(html.contains("<th>Writes to</th>"));
+    assert!(html.contains("<td>0x500010</td>"));
+}
+
+#[test]
+fn sessions_of_different_guests() {
+    let mut first = TraceBuilder::new();
+    first.start_interned_timer(0x0040_0000, 5).stop_timer();
+    let mut elf = guest_elf_with_channels(&first, true);
+    elf.segments.push((0x0040_0000, b"Hello".to_vec()));
+
+    // the second guest has other channels, and its ELF is stripped, so they only come from the handshake
+    let mut second = TraceBuilder::new();
+    second.trace_msg_channel = 0x0031_0000;
+    second.trace_msg_len_channel = 0x0031_0200;
+    second
+        .handshake()
+        .start_timer("Second")
+        .nops(5)
+        .stop_timer();
+
+    let mut tracer = CycleTracer::default();
+    tracer.begin_session("a");
+    tracer.guest_elf = Some(elf);
+    assert!(tracer.find_channels());
+    first.feed(&mut tracer);
+
+    tracer.begin_session("b");
+    assert!(tracer.guest_elf.is_none());
+    assert_eq!(tracer.init_state_machine, 0);
+    tracer.guest_elf = Some(GuestElf {
+        segments: vec![],
+        symbols: Default::default(),
+    });
+    assert!(!tracer.find_channels());
+    second.feed(&mut tracer);
+    tracer.end_session();
+
+    let a = tracer.session("a").unwrap();
+    assert_eq!(a.finished_records[0].name, "Hello");
+    assert!(a.guest_elf.is_some());
+    let b = tracer.session("b").unwrap();
+    assert_eq!(b.finished_records.len(), 1);
+    assert_eq!(b.finished_records[0].name, "Second");
+    assert_eq!(b.trace_msg_channel, 0x0031_0000);
+    assert_eq!(b.trace_msg_len_channel, 0x0031_0200);
+}